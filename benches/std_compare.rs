@@ -1,3 +1,4 @@
+#![allow(clippy::unused_io_amount)]
 #![feature(test)]
 
 extern crate seek_bufread;
@@ -7,7 +8,7 @@ use seek_bufread::BufReader;
 
 use test::Bencher;
 use std::fs::{self, File};
-use std::io::{self, Cursor, Read, Write, Seek, SeekFrom};
+use std::io::{self, BufRead, Cursor, Read, Write, Seek, SeekFrom};
 
 #[bench]
 fn read_10mb_default_from_cursor(b: &mut Bencher) {
@@ -168,3 +169,105 @@ fn read_seek_10mb_default_from_file_std(b: &mut Bencher) {
     });
     fs::remove_file("foo.txt").unwrap();
 }
+
+#[bench]
+fn read_10mb_chunked_from_file(b: &mut Bencher) {
+    let mut f = File::create("foo.txt").unwrap();
+    f.write_all(&vec![0; 10000000]).unwrap();
+    b.iter(|| {
+        let mut reader = BufReader::new(File::open("foo.txt").unwrap());
+        let mut buf = vec![0; 1000000];
+        while reader.read(&mut buf).unwrap() > 0 {}
+    });
+    fs::remove_file("foo.txt").unwrap();
+}
+
+#[bench]
+fn read_10mb_chunked_from_file_std(b: &mut Bencher) {
+    let mut f = File::create("foo.txt").unwrap();
+    f.write_all(&vec![0; 10000000]).unwrap();
+    b.iter(|| {
+        let mut reader = io::BufReader::new(File::open("foo.txt").unwrap());
+        let mut buf = vec![0; 1000000];
+        while reader.read(&mut buf).unwrap() > 0 {}
+    });
+    fs::remove_file("foo.txt").unwrap();
+}
+
+#[bench]
+fn read_u32_le_10mb_from_cursor(b: &mut Bencher) {
+    b.iter(|| {
+        let mut reader = BufReader::new(Cursor::new(vec![1; 10000000]));
+        let mut sum = 0u32;
+        for _ in 0..2500000 {
+            sum = sum.wrapping_add(reader.read_u32_le().unwrap());
+        }
+        sum
+    });
+}
+
+#[bench]
+fn read_u32_le_bytewise_10mb_from_cursor(b: &mut Bencher) {
+    b.iter(|| {
+        let mut reader = BufReader::new(Cursor::new(vec![1; 10000000]));
+        let mut sum = 0u32;
+        for _ in 0..2500000 {
+            let mut value = 0u32;
+            for i in 0..4 {
+                let mut byte = [0];
+                reader.read(&mut byte).unwrap();
+                value |= (byte[0] as u32) << (8 * i);
+            }
+            sum = sum.wrapping_add(value);
+        }
+        sum
+    });
+}
+
+fn haystack_10mb() -> Vec<u8> {
+    let mut data: Vec<u8> = (0..10000000u32).map(|i| (i % 251) as u8).collect();
+    let len = data.len();
+    data[len - 16..len - 8].copy_from_slice(b"SYNCMARK");
+    data
+}
+
+#[bench]
+fn find_10mb_from_cursor(b: &mut Bencher) {
+    let data = haystack_10mb();
+    b.iter(|| {
+        let mut reader = BufReader::new(Cursor::new(&data[..]));
+        assert_eq!(reader.find(b"SYNCMARK").unwrap(), Some(9999984));
+    });
+}
+
+#[bench]
+fn find_naive_window_scan_10mb_from_cursor(b: &mut Bencher) {
+    let data = haystack_10mb();
+    b.iter(|| {
+        let mut reader = BufReader::new(Cursor::new(&data[..]));
+        let pattern = b"SYNCMARK";
+        let mut found = None;
+        let mut window = Vec::new();
+        loop {
+            let pos = reader.position() - window.len() as u64;
+            let n = {
+                let buf = reader.fill_buf().unwrap();
+                window.extend_from_slice(buf);
+                buf.len()
+            };
+            if n == 0 {
+                break;
+            }
+            reader.consume(n);
+            if let Some(i) = window.windows(pattern.len()).position(|w| w == pattern) {
+                found = Some(pos + i as u64);
+                break;
+            }
+            // Keep the overlap for matches straddling two windows
+            let overlap = window.len().min(pattern.len() - 1);
+            window.drain(..window.len() - overlap);
+        }
+        assert_eq!(found, Some(9999984));
+    });
+}
+
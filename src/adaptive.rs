@@ -0,0 +1,56 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Fill size policy backing `BufReader::adaptive`.
+//!
+//! The fill size is doubled after `GROW_STREAK` consecutive sequential fills
+//! and halved on every fill which follows a seek outside of the buffer.
+
+use std::mem;
+
+/// Number of sequential fills after which the fill size is doubled.
+const GROW_STREAK: u32 = 2;
+
+pub struct Adaptive {
+    min: usize,            // lower bound of the fill size
+    max: usize,            // upper bound of the fill size
+    streak: u32,           // sequential fills since the last resize
+    seeked: bool,          // set if the buffer was left by a seek since the last fill
+}
+
+impl Adaptive {
+    pub fn new(min: usize, max: usize) -> Adaptive {
+        assert!(min > 0, "minimum fill size must be non-zero");
+        assert!(min <= max, "minimum fill size must not exceed the maximum");
+        Adaptive { min, max, streak: 0, seeked: false }
+    }
+
+    /// Limits `size` to the configured bounds.
+    pub fn clamp(&self, size: usize) -> usize {
+        size.max(self.min).min(self.max)
+    }
+
+    /// Records a seek outside of the buffer.
+    pub fn seeked(&mut self) {
+        self.seeked = true;
+    }
+
+    /// Returns the size of the next fill, given the size of the previous one.
+    pub fn next_size(&mut self, current: usize) -> usize {
+        if mem::replace(&mut self.seeked, false) {
+            self.streak = 0;
+            return self.clamp(current / 2);
+        }
+        self.streak += 1;
+        if self.streak >= GROW_STREAK {
+            self.streak = 0;
+            return self.clamp(current.saturating_mul(2));
+        }
+        self.clamp(current)
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Bit-level reading on top of a `BufReader`.
+//!
+//! Only the partially consumed byte is held by the `BitReader`, whole bytes
+//! are read from the `BufReader` and bit seeks become byte seeks on it.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use BufReader;
+
+/// Selects the order in which the bits of a byte are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    /// The most significant bit comes first, as in most video and image codecs.
+    MsbFirst,
+    /// The least significant bit comes first, as in DEFLATE.
+    LsbFirst,
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Returns a `BitReader` which starts at the current byte position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::{BitOrder, BufReader};
+    ///
+    /// let mut reader = BufReader::new(Cursor::new([0b1011_0001, 0xff]));
+    /// let mut bits = reader.bits(BitOrder::MsbFirst);
+    /// assert_eq!(bits.read_bits(3).unwrap(), 0b101);
+    /// assert_eq!(bits.read_bits(7).unwrap(), 0b1_0001_11);
+    /// assert_eq!(bits.position(), 10);
+    /// ```
+    pub fn bits(&mut self, order: BitOrder) -> BitReader<'_, R> {
+        BitReader { reader: self, order, byte: 0, bits_left: 0 }
+    }
+}
+
+/// Reads values of up to 64 bits from a `BufReader`.
+///
+/// Dropping the `BitReader` leaves the `BufReader` behind the last byte
+/// of which any bits were read.
+/// This struct is created by `BufReader::bits`.
+pub struct BitReader<'a, R: 'a> {
+    reader: &'a mut BufReader<R>,
+    order: BitOrder,
+    byte: u8,           // byte before the position of `reader`
+    bits_left: u32,     // unread bits of `byte`
+}
+
+impl<'a, R: Read + Seek> BitReader<'a, R> {
+    /// Returns the position in bits from the start of the stream.
+    pub fn position(&self) -> u64 {
+        self.reader.position() * 8 - self.bits_left as u64
+    }
+
+    /// Reads `n` bits and returns them as the low bits of an `u64`.
+    ///
+    /// With `BitOrder::LsbFirst` the first bit read becomes the least significant one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than 64.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `UnexpectedEof` if the stream ends first,
+    /// the position is unchanged then.
+    pub fn read_bits(&mut self, n: u32) -> io::Result<u64> {
+        assert!(n <= 64, "cannot read more than 64 bits at once");
+        let start = self.position();
+        let mut value = 0u64;
+        let mut done = 0;
+        while done < n {
+            if self.bits_left == 0 {
+                match self.reader.read_u8() {
+                    Ok(byte) => self.byte = byte,
+                    Err(e) => {
+                        self.seek_bits(SeekFrom::Start(start))?;
+                        return Err(e);
+                    }
+                }
+                self.bits_left = 8;
+            }
+            let k = (n - done).min(self.bits_left);
+            let mask = (1u64 << k) - 1;
+            match self.order {
+                BitOrder::MsbFirst => {
+                    let chunk = (self.byte >> (self.bits_left - k)) as u64 & mask;
+                    value = value << k | chunk;
+                }
+                BitOrder::LsbFirst => {
+                    let chunk = (self.byte >> (8 - self.bits_left)) as u64 & mask;
+                    value |= chunk << done;
+                }
+            }
+            self.bits_left -= k;
+            done += k;
+        }
+        Ok(value)
+    }
+
+    /// Reads a single bit.
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        self.read_bits(1).map(|b| b == 1)
+    }
+
+    /// Skips the rest of a partially read byte and returns the number of bits skipped.
+    pub fn align_to_byte(&mut self) -> u32 {
+        let skipped = self.bits_left;
+        self.bits_left = 0;
+        skipped
+    }
+
+    /// Seeks to an offset in bits and returns the new bit position.
+    ///
+    /// The byte containing the target is reached with a byte seek on the `BufReader`,
+    /// which is served from its buffer if possible.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `UnexpectedEof` if the target lies inside a byte
+    /// behind the end of the stream, the position is unchanged then.
+    pub fn seek_bits(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => (n, 0),
+            SeekFrom::Current(n) => (self.position(), n),
+            SeekFrom::End(n) => (self.reader.len()? * 8, n),
+        };
+        let target = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "invalid seek to a negative or overflowing position")),
+        };
+
+        let start = self.position();
+        if let Err(e) = self.move_to(target) {
+            self.move_to(start)?;
+            return Err(e);
+        }
+        Ok(target)
+    }
+
+    /// Moves to the bit position `target`, loading the byte containing it if required
+    fn move_to(&mut self, target: u64) -> io::Result<()> {
+        self.bits_left = 0;
+        self.reader.seek(SeekFrom::Start(target / 8))?;
+        let bit = (target % 8) as u32;
+        if bit > 0 {
+            self.byte = self.reader.read_u8()?;
+            self.bits_left = 8 - bit;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Cursor};
+    use tests::counting_cursor;
+
+    fn reader(data: &[u8]) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(4, Cursor::new(data.to_vec()))
+    }
+
+    #[test]
+    fn msb_first() {
+        let mut r = reader(&[0b1100_1010, 0b0111_0000, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0]);
+        let mut bits = r.bits(BitOrder::MsbFirst);
+        assert!(bits.read_bit().unwrap());
+        assert_eq!(bits.read_bits(4).unwrap(), 0b1001);
+        assert_eq!(bits.read_bits(6).unwrap(), 0b010_011);
+        assert_eq!(bits.align_to_byte(), 5);
+        assert_eq!(bits.read_bits(0).unwrap(), 0);
+        assert_eq!(bits.read_bits(64).unwrap(), 0x123456789abcdef0);
+        assert_eq!(bits.read_bit().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn lsb_first() {
+        let mut r = reader(&[0b1100_1010, 0b0111_0001, 0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
+        let mut bits = r.bits(BitOrder::LsbFirst);
+        assert!(!bits.read_bit().unwrap());
+        assert_eq!(bits.read_bits(4).unwrap(), 0b0101);
+        assert_eq!(bits.read_bits(6).unwrap(), 0b001_110);
+        bits.align_to_byte();
+        assert_eq!(bits.read_bits(64).unwrap(), 0x123456789abcdef0);
+    }
+
+    #[test]
+    fn eof_restores_position() {
+        let mut r = reader(&[0xff, 0xff]);
+        let mut bits = r.bits(BitOrder::MsbFirst);
+        bits.read_bits(3).unwrap();
+        assert_eq!(bits.read_bits(16).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(bits.position(), 3);
+        assert_eq!(bits.read_bits(13).unwrap(), 0x1fff);
+    }
+
+    #[test]
+    fn seek_bits() {
+        let mut r = BufReader::with_capacity(16, counting_cursor(16));
+        r.fill_buf().unwrap();
+        {
+            let mut bits = r.bits(BitOrder::MsbFirst);
+            // Byte 5 is 0b0000_0101
+            assert_eq!(bits.seek_bits(SeekFrom::Start(43)).unwrap(), 43);
+            assert_eq!(bits.read_bits(5).unwrap(), 0b00101);
+            assert_eq!(bits.seek_bits(SeekFrom::Current(-6)).unwrap(), 42);
+            assert_eq!(bits.read_bits(2).unwrap(), 0b00);
+            assert_eq!(bits.seek_bits(SeekFrom::End(-4)).unwrap(), 124);
+            assert_eq!(bits.read_bits(4).unwrap(), 0xf);
+            assert!(bits.seek_bits(SeekFrom::Current(-200)).is_err());
+            assert_eq!(bits.position(), 128);
+        }
+        assert_eq!(r.inner.reads, 1);
+
+        // A target inside a byte behind the end doesn't move the position
+        let mut bits = r.bits(BitOrder::MsbFirst);
+        bits.seek_bits(SeekFrom::Start(43)).unwrap();
+        let err = bits.seek_bits(SeekFrom::Start(203)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(bits.position(), 43);
+        assert_eq!(bits.read_bits(5).unwrap(), 0b00101);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Least-recently-used page cache backing `BufReader::with_block_cache`.
+//!
+//! The page which is currently read from lives in the internal buffer of `BufReader`,
+//! all other pages are parked here. Switching pages swaps the boxed slices,
+//! so no bytes are copied.
+
+use std::mem;
+
+/// Hit and miss counters of a `BufReader` in block cache mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of page lookups served from memory.
+    pub hits: u64,
+    /// Number of page lookups which had to invoke the underlying reader.
+    pub misses: u64,
+}
+
+struct Block {
+    index: u64,            // block index (offset / block_size)
+    data: Box<[u8]>,       // page contents
+    len: usize,            // valid bytes in data
+    last_used: u64,        // tick of the last access
+}
+
+pub struct BlockCache {
+    block_size: usize,              // size of a single page
+    max_blocks: usize,              // pages held in total, including the current one
+    blocks: Vec<Block>,             // parked pages
+    current: Option<(u64, usize)>,  // index and length of the page held by the reader
+    tick: u64,                      // access counter used for LRU ordering
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    pub fn new(block_size: usize, max_blocks: usize) -> BlockCache {
+        assert!(block_size > 0, "block size must be non-zero");
+        assert!(max_blocks > 0, "block cache must hold at least one block");
+        BlockCache {
+            block_size,
+            max_blocks,
+            blocks: Vec::with_capacity(max_blocks),
+            current: None,
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn block_size(&self) -> usize { self.block_size }
+
+    pub fn stats(&self) -> CacheStats { self.stats }
+
+    /// Drops all parked pages and forgets the page held by the reader.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.blocks.shrink_to_fit();
+        self.current = None;
+    }
+
+    /// Changes the page size, all cached pages are dropped.
+    pub fn set_block_size(&mut self, block_size: usize) {
+        assert!(block_size > 0, "block size must be non-zero");
+        self.clear();
+        self.block_size = block_size;
+    }
+
+    /// Makes `buf` hold the page with the given `index`.
+    ///
+    /// Returns the length of the page on a hit. On a miss `buf` holds a recycled
+    /// allocation which has to be filled by the caller and registered with `loaded`.
+    pub fn fetch(&mut self, index: u64, buf: &mut Box<[u8]>) -> Option<usize> {
+        self.tick += 1;
+        if let Some((current, len)) = self.current {
+            if current == index {
+                self.stats.hits += 1;
+                return Some(len);
+            }
+            // Park the current page
+            let data = mem::replace(buf, Box::new([]));
+            self.blocks.push(Block { index: current, data, len, last_used: self.tick });
+            self.current = None;
+        }
+
+        if let Some(i) = self.blocks.iter().position(|b| b.index == index) {
+            let block = self.blocks.swap_remove(i);
+            *buf = block.data;
+            self.current = Some((index, block.len));
+            self.stats.hits += 1;
+            return Some(block.len);
+        }
+
+        self.stats.misses += 1;
+        if buf.len() != self.block_size {
+            if self.blocks.len() >= self.max_blocks {
+                // Recycle the least recently used page
+                let lru = self.blocks.iter()
+                    .enumerate()
+                    .min_by_key(|&(_, b)| b.last_used)
+                    .map(|(i, _)| i)
+                    .unwrap();
+                *buf = self.blocks.swap_remove(lru).data;
+            } else {
+                *buf = vec![0; self.block_size].into_boxed_slice();
+            }
+        }
+        None
+    }
+
+    /// Forgets the page held by the reader, e.g. after its buffer was rearranged.
+    pub fn detach(&mut self) {
+        self.current = None;
+    }
+
+    /// Registers the page read into the reader buffer after a miss.
+    pub fn loaded(&mut self, index: u64, len: usize) {
+        self.current = Some((index, len));
+    }
+}
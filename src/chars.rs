@@ -0,0 +1,294 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Reading UTF-8 encoded chars forwards and backwards.
+//!
+//! Chars are decoded straight from the internal buffer. A char which straddles
+//! the end of the window is made contiguous first, like `peek` does.
+
+use std::char::REPLACEMENT_CHARACTER;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str;
+
+use {BufReader, Utf8Policy};
+
+/// Maximum length of an UTF-8 encoded char.
+const MAX_WIDTH: usize = 4;
+
+/// Returns the length of the sequence introduced by `lead`, 1 for invalid lead bytes
+fn width(lead: u8) -> usize {
+    match lead {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 1,
+    }
+}
+
+fn is_continuation(b: u8) -> bool { b & 0xc0 == 0x80 }
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Decodes the char at the current position without consuming it.
+    /// Returns the char, or `None` for an invalid sequence, and its length in bytes.
+    fn decode_char(&mut self) -> io::Result<Option<(Option<char>, usize)>> {
+        self.fill_for_peek()?;
+        if self.available() == 0 {
+            return Ok(None);
+        }
+        let n = width(self.buf[self.buf_pos]);
+        if self.available() < n {
+            // The sequence straddles the end of the window, this grows the buffer
+            // if required but leaves the fill size alone
+            self.fill_contiguous(n)?;
+        }
+        let bytes = &self.buf[self.buf_pos..self.cap.min(self.buf_pos + n)];
+        Ok(Some(match str::from_utf8(bytes) {
+            Ok(s) => (s.chars().next(), bytes.len()),
+            // Truncated sequences at the end of the stream have no error length
+            Err(e) => (None, e.error_len().unwrap_or(bytes.len())),
+        }))
+    }
+
+    /// Applies the `Utf8Policy` to a decoded char
+    fn check_char(&self, c: Option<char>) -> io::Result<char> {
+        match (c, self.utf8_policy) {
+            (Some(c), _) => Ok(c),
+            (None, Utf8Policy::Replace) => Ok(REPLACEMENT_CHARACTER),
+            (None, Utf8Policy::Error) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                            "stream did not contain valid UTF-8")),
+        }
+    }
+
+    /// Reads the next UTF-8 encoded char, or `None` at the end of the stream.
+    ///
+    /// Buffers smaller than 4 bytes are grown to hold a whole char.
+    ///
+    /// # Errors
+    ///
+    /// With `Utf8Policy::Error` an invalid sequence returns an error of the kind
+    /// `InvalidData`, the position is moved behind it nevertheless.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("aé€"));
+    /// assert_eq!(reader.read_char().unwrap(), Some('a'));
+    /// assert_eq!(reader.read_char().unwrap(), Some('é'));
+    /// assert_eq!(reader.peek_char().unwrap(), Some('€'));
+    /// assert_eq!(reader.read_char().unwrap(), Some('€'));
+    /// assert_eq!(reader.read_char().unwrap(), None);
+    /// ```
+    pub fn read_char(&mut self) -> io::Result<Option<char>> {
+        match self.decode_char()? {
+            Some((c, n)) => {
+                self.buf_pos += n;
+                self.absolute_pos += n as u64;
+                self.check_char(c).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the next char without consuming it, or `None` at the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// See `read_char`, the position is unchanged.
+    pub fn peek_char(&mut self) -> io::Result<Option<char>> {
+        match self.decode_char()? {
+            Some((c, _)) => self.check_char(c).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Steps back over the char before the current position and returns it,
+    /// or `None` at the start of the stream.
+    ///
+    /// The char is decoded from the buffer and the position is moved within it
+    /// if possible. Invalid bytes are stepped over one at a time.
+    ///
+    /// # Errors
+    ///
+    /// With `Utf8Policy::Error` an invalid byte returns an error of the kind
+    /// `InvalidData`, the position is moved before it nevertheless.
+    /// Other errors leave the position unchanged. Behind the end of the stream
+    /// the char before its end is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Seek, SeekFrom};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("añ"));
+    /// reader.seek(SeekFrom::End(0)).unwrap();
+    /// assert_eq!(reader.unread_char().unwrap(), Some('ñ'));
+    /// assert_eq!(reader.position(), 1);
+    /// ```
+    pub fn unread_char(&mut self) -> io::Result<Option<char>> {
+        if self.buf_pos == 0 && self.absolute_pos > 0 && self.history.is_none() {
+            // Behind the end of the stream, start at its end instead
+            self.clamp_to_end()?;
+        }
+        let pos = self.absolute_pos;
+        if pos == 0 {
+            return Ok(None);
+        }
+        let k = pos.min(MAX_WIDTH as u64) as usize;
+        let mut tail = [0; MAX_WIDTH];
+        if self.buf_pos >= k {
+            tail[..k].copy_from_slice(&self.buf[self.buf_pos - k..self.buf_pos]);
+        } else {
+            // The char straddles the start of the window
+            self.seek(SeekFrom::Start(pos - k as u64))?;
+            if let Err(e) = self.read_exact(&mut tail[..k]) {
+                self.seek(SeekFrom::Start(pos))?;
+                return Err(e);
+            }
+        }
+        let tail = &tail[..k];
+
+        let c = tail.iter().rposition(|&b| !is_continuation(b))
+            .and_then(|i| str::from_utf8(&tail[i..]).ok())
+            .and_then(|s| s.chars().next());
+        let n = c.map_or(1, |c| c.len_utf8());
+        self.seek_backward(n as u64)?;
+        self.check_char(c).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tests::CountingReader;
+
+    fn reader(data: &[u8], cap: usize) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(cap, Cursor::new(data.to_vec()))
+    }
+
+    fn read_all(reader: &mut BufReader<Cursor<Vec<u8>>>) -> String {
+        let mut s = String::new();
+        while let Some(c) = reader.read_char().unwrap() {
+            s.push(c);
+        }
+        s
+    }
+
+    #[test]
+    fn across_refills() {
+        let text = "a€b𝄞cé";
+        for cap in 1..8 {
+            let mut r = reader(text.as_bytes(), cap);
+            assert_eq!(read_all(&mut r), text, "capacity {}", cap);
+
+            let mut rev = String::new();
+            while let Some(c) = r.unread_char().unwrap() {
+                rev.push(c);
+            }
+            assert_eq!(rev, text.chars().rev().collect::<String>(), "capacity {}", cap);
+            assert_eq!(r.position(), 0);
+        }
+    }
+
+    #[test]
+    fn unread_behind_end() {
+        let text = "ab€d";
+        let mut r = reader(text.as_bytes(), 4);
+        r.seek(SeekFrom::Start(10)).unwrap();
+        assert_eq!(r.unread_char().unwrap(), Some('d'));
+        assert_eq!(r.position(), 5);
+        assert_eq!(r.unread_char().unwrap(), Some('€'));
+        assert_eq!(r.position(), 2);
+
+        // A length hint covering the position is trusted, the failed read doesn't move it
+        let mut r = reader(text.as_bytes(), 4);
+        r.set_len_hint(10);
+        r.seek(SeekFrom::Start(8)).unwrap();
+        assert_eq!(r.unread_char().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(r.position(), 8);
+    }
+
+    #[test]
+    fn block_cache() {
+        let text = "ab€cd𝄞ef€";
+        let mut r = BufReader::with_block_cache(4, 2, Cursor::new(text.as_bytes().to_vec()));
+        assert_eq!(read_all(&mut r), text);
+        r.seek(SeekFrom::Start(5)).unwrap();
+        assert_eq!(r.unread_char().unwrap(), Some('€'));
+        assert_eq!(r.read_char().unwrap(), Some('€'));
+        assert_eq!(r.read_char().unwrap(), Some('c'));
+    }
+
+    #[test]
+    fn invalid_sequences() {
+        // Lone continuation byte, truncated sequence, overlong encoding, truncation at EOF
+        let data = b"a\x80b\xe2\x82c\xc0\xafd\xf0\x9f";
+        let mut r = reader(data, 16).utf8_policy(Utf8Policy::Replace);
+        assert_eq!(read_all(&mut r), "a\u{fffd}b\u{fffd}c\u{fffd}\u{fffd}d\u{fffd}");
+        assert_eq!(String::from_utf8_lossy(data), "a\u{fffd}b\u{fffd}c\u{fffd}\u{fffd}d\u{fffd}");
+
+        let mut r = reader(data, 16);
+        assert_eq!(r.read_char().unwrap(), Some('a'));
+        assert_eq!(r.peek_char().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(r.position(), 1);
+        assert_eq!(r.read_char().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(r.read_char().unwrap(), Some('b'));
+        assert_eq!(r.unread_char().unwrap(), Some('b'));
+        assert_eq!(r.unread_char().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(r.position(), 1);
+    }
+
+    #[test]
+    fn unread_within_buffer() {
+        let text = "xyzäöü";
+        let data = text.as_bytes().to_vec();
+        let mut r = BufReader::with_capacity(16, CountingReader::new(Cursor::new(data)));
+        // Stops short of EOF, hitting it replaces the window with an empty one
+        for _ in text.chars() {
+            r.read_char().unwrap();
+        }
+        let (reads, seeks) = (r.inner.reads, r.inner.seeks);
+        assert_eq!(r.unread_char().unwrap(), Some('ü'));
+        assert_eq!(r.unread_char().unwrap(), Some('ö'));
+        assert_eq!(r.read_char().unwrap(), Some('ö'));
+        assert_eq!((r.inner.reads, r.inner.seeks), (reads, seeks));
+    }
+
+    #[test]
+    fn keeps_fill_size() {
+        let mut r = reader("a€b".as_bytes(), 64);
+        r.read_char().unwrap();
+        r.shrink_to_fit();
+        assert_eq!(r.read_char().unwrap(), Some('€'));
+        assert_eq!(r.fill_size(), 64);
+        assert_eq!(r.capacity(), 64);
+
+        let mut r = reader("a€b".as_bytes(), 64);
+        r.shrink_to_fit();
+        assert_eq!(r.read_char().unwrap(), Some('a'));
+        assert_eq!(r.fill_size(), 64);
+        assert_eq!(r.capacity(), 64);
+    }
+
+    #[test]
+    fn block_cache_after_seek() {
+        let data: Vec<u8> = (0..64).collect();
+        let mut r = BufReader::with_block_cache(8, 2, Cursor::new(data));
+        let mut buf = [0; 4];
+        r.read_exact(&mut buf[..1]).unwrap();
+        r.seek(SeekFrom::Start(20)).unwrap();
+        assert_eq!(r.read_char().unwrap(), Some('\u{14}'));
+        r.seek(SeekFrom::Start(0)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3]);
+    }
+}
@@ -9,10 +9,10 @@
 // except according to those terms.
 //! The `BufReader` is a drop-in replacement for `std::io::BufReader` with seeking support.
 //!
-//! If `.seek(SeekFrom::Current(n))` is called and `n` is in range of the internal buffer the
-//! underlying reader is not invoked. This has the side effect that you can no longer access
-//! the underlying buffer directly after being consumed by `BufReader`,
-//! because its position could be out of sync.
+//! If `.seek(SeekFrom::Current(n))` or `.seek(SeekFrom::Start(n))` is called and the target
+//! is in range of the internal buffer the underlying reader is not invoked.
+//! This has the side effect that you can no longer access the underlying buffer
+//! directly after being consumed by `BufReader`, because its position could be out of sync.
 //!
 //! # Examples
 //!
@@ -31,11 +31,95 @@
 //! assert_eq!(buf, [4, 5, 6, 7, 8, 9, 10, 11]);
 //! ```
 
+#[cfg(feature = "regex")]
+extern crate regex;
+
 use std::fmt;
 use std::io::{self, BufRead, Read, Seek, SeekFrom};
 
+mod adaptive;
+mod bits;
+mod cache;
+mod chars;
+mod line_index;
+mod lines;
+mod mark;
+mod primitives;
+mod readahead;
+#[cfg(feature = "regex")]
+mod regex_search;
+mod search;
+mod slice;
+mod tracker;
+mod unseekable;
+mod varint;
+
+use adaptive::Adaptive;
+use cache::BlockCache;
+use tracker::LineTracker;
+pub use bits::{BitOrder, BitReader};
+pub use cache::CacheStats;
+pub use line_index::LineIndex;
+pub use lines::RevLines;
+pub use mark::Mark;
+pub use readahead::ReadAhead;
+#[cfg(feature = "regex")]
+pub use regex_search::{Match, Matches};
+pub use slice::SubReader;
+pub use unseekable::Unseekable;
+
 const DEFAULT_BUF_SIZE: usize = 8 * 1024;
 
+/// Selects where the window is placed when filling the buffer after seeking backwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillPlacement {
+    /// The window starts at the target position (default).
+    Forward,
+    /// The window is centered on the target position.
+    Center,
+    /// The window ends where the previous window started. If the target lies further
+    /// back than one buffer length, the window ends right behind the target instead.
+    ///
+    /// Stepping backwards through a stream takes one read per buffer length.
+    Backward,
+}
+
+/// Selects how `BufReader::peek` handles requests larger than the buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeekPolicy {
+    /// Returns an error of the kind `InvalidInput` (default).
+    Error,
+    /// Grows the buffer to the requested size.
+    ///
+    /// In block cache mode only the buffer holding the current page grows,
+    /// the block size and the cached pages are kept.
+    Grow,
+}
+
+/// Selects how `BufReader::read_char` and related methods handle invalid UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Returns an error of the kind `InvalidData` (default).
+    Error,
+    /// Returns `U+FFFD REPLACEMENT CHARACTER` for every invalid sequence,
+    /// like `String::from_utf8_lossy`.
+    Replace,
+}
+
+/// Selects how `Read::read` behaves on a `BufReader`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Reads until the caller's buffer is full or EOF is reached (default).
+    Fill,
+    /// Invokes the inner reader at most once per call, like `std::io::BufReader`.
+    ///
+    /// This returns short reads from pipes, sockets or interactive inputs immediately.
+    /// Fills which start before the current position, in block cache mode or with a
+    /// `FillPlacement` other than `Forward`, only read again if a short read doesn't
+    /// reach the position.
+    Single,
+}
+
 /// The `BufReader` struct adds buffering to any reader.
 ///
 /// It can be excessively inefficient to work directly with a `Read` instance.
@@ -51,11 +135,11 @@ const DEFAULT_BUF_SIZE: usize = 8 * 1024;
 /// use seek_bufread::BufReader;
 ///
 /// # fn foo() -> std::io::Result<()> {
-/// let mut f = try!(File::open("log.txt"));
+/// let mut f = File::open("log.txt")?;
 /// let mut reader = BufReader::new(f);
 ///
 /// let mut line = String::new();
-/// let len = try!(reader.read_line(&mut line));
+/// let len = reader.read_line(&mut line)?;
 /// println!("First line is {} bytes long", len);
 /// # Ok(())
 /// # }
@@ -66,6 +150,30 @@ pub struct BufReader<R> {
     buf_pos: usize,        // position within buf
     cap: usize,            // buf capacity
     absolute_pos: u64,     // absolute position
+    /// Parked pages in block cache mode.
+    cache: Option<BlockCache>,
+    len: Option<u64>,      // cached stream length
+    /// Position of the inner reader, `None` if unknown.
+    inner_pos: Option<u64>,
+    read_mode: ReadMode,   // semantics of `Read::read`
+    buf_size: usize,       // number of bytes requested per fill
+    /// Fill size policy.
+    adaptive: Option<Adaptive>,
+    /// Window placement after seeking backwards.
+    placement: FillPlacement,
+    /// Start of the window left by seeking backwards.
+    backward_from: Option<u64>,
+    /// Handling of peeks larger than the buffer.
+    peek_policy: PeekPolicy,
+    marks: Vec<u64>,       // positions of active marks, oldest first
+    /// Read limit of pinned marks, `None` if marks are not pinned.
+    mark_limit: Option<usize>,
+    /// Bytes kept behind the position, `None` if the reader is seekable.
+    history: Option<usize>,
+    /// Newline counts for `line_col`.
+    tracker: Option<LineTracker>,
+    /// Handling of invalid UTF-8 in `read_char`.
+    utf8_policy: Utf8Policy,
 }
 
 impl<R: Read + Seek> BufReader<R> {
@@ -79,7 +187,7 @@ impl<R: Read + Seek> BufReader<R> {
     /// use seek_bufread::BufReader;
     ///
     /// # fn foo() -> std::io::Result<()> {
-    /// let mut f = try!(File::open("log.txt"));
+    /// let mut f = File::open("log.txt")?;
     /// let mut reader = BufReader::new(f);
     /// # Ok(())
     /// # }
@@ -99,83 +207,785 @@ impl<R: Read + Seek> BufReader<R> {
     /// use seek_bufread::BufReader;
     ///
     /// # fn foo() -> std::io::Result<()> {
-    /// let mut f = try!(File::open("log.txt"));
+    /// let mut f = File::open("log.txt")?;
     /// let mut reader = BufReader::with_capacity(10, f);
     /// # Ok(())
     /// # }
     /// ```
     pub fn with_capacity(cap: usize, inner: R) -> BufReader<R> {
         BufReader {
-            inner: inner,
+            inner,
             buf: vec![0; cap].into_boxed_slice(),
             buf_pos: 0,
             cap: 0,
             absolute_pos: 0,
+            cache: None,
+            len: None,
+            inner_pos: Some(0),
+            read_mode: ReadMode::Fill,
+            buf_size: cap,
+            adaptive: None,
+            placement: FillPlacement::Forward,
+            backward_from: None,
+            peek_policy: PeekPolicy::Error,
+            marks: Vec::new(),
+            mark_limit: None,
+            history: None,
+            tracker: None,
+            utf8_policy: Utf8Policy::Error,
         }
     }
 
+    /// Creates a new `BufReader` which keeps up to `blocks` pages of `block_size` bytes
+    /// in a least-recently-used cache.
+    ///
+    /// Pages are aligned to multiples of `block_size`. Reading from or seeking to an offset
+    /// inside any cached page doesn't invoke the underlying reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` or `blocks` is zero.
+    ///
+    /// # Examples
+    ///
+    /// Caching four pages of 4096 bytes each:
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use seek_bufread::BufReader;
+    ///
+    /// # fn foo() -> std::io::Result<()> {
+    /// let mut f = File::open("log.txt")?;
+    /// let mut reader = BufReader::with_block_cache(4096, 4, f);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_block_cache(block_size: usize, blocks: usize, inner: R) -> BufReader<R> {
+        let cache = BlockCache::new(block_size, blocks);
+        let mut reader = BufReader::with_capacity(block_size, inner);
+        reader.cache = Some(cache);
+        reader
+    }
+
+    /// Sets the semantics of `Read::read`, see `ReadMode`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::{BufReader, ReadMode};
+    ///
+    /// let inner = Cursor::new([0, 1, 2, 3]);
+    /// let reader = BufReader::new(inner).read_mode(ReadMode::Single);
+    /// ```
+    pub fn read_mode(mut self, mode: ReadMode) -> BufReader<R> {
+        self.read_mode = mode;
+        self
+    }
+
+    /// Adapts the fill size to the observed access pattern, within `min` and `max` bytes.
+    ///
+    /// The fill size grows on sequential reads and shrinks after seeks outside of the buffer.
+    /// Use `fill_size` to check the size currently in use.
+    /// This has no effect in block cache mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is zero or greater than `max`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let inner = Cursor::new(vec![0; 1024 * 1024]);
+    /// let reader = BufReader::new(inner).adaptive(4 * 1024, 256 * 1024);
+    /// assert_eq!(reader.fill_size(), 8 * 1024);
+    /// ```
+    pub fn adaptive(mut self, min: usize, max: usize) -> BufReader<R> {
+        let adaptive = Adaptive::new(min, max);
+        self.buf_size = adaptive.clamp(self.buf_size);
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Sets where the window is placed when filling the buffer after seeking backwards,
+    /// see `FillPlacement`. This has no effect in block cache mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read, Seek, SeekFrom};
+    /// use seek_bufread::{BufReader, FillPlacement};
+    ///
+    /// let inner = Cursor::new((0..64).collect::<Vec<u8>>());
+    /// let mut reader = BufReader::with_capacity(16, inner)
+    ///     .fill_placement(FillPlacement::Backward);
+    ///
+    /// reader.seek(SeekFrom::End(-4)).unwrap();
+    /// let mut buf = [0; 4];
+    /// reader.read(&mut buf).unwrap();
+    /// assert_eq!(buf, [60, 61, 62, 63]);
+    ///
+    /// // Fills the window 44..60, which ends where the previous one started
+    /// reader.seek(SeekFrom::Current(-8)).unwrap();
+    /// reader.read(&mut buf).unwrap();
+    /// assert_eq!(buf, [56, 57, 58, 59]);
+    /// assert_eq!(reader.buffer(), []);
+    /// reader.seek(SeekFrom::Start(44)).unwrap();
+    /// assert_eq!(reader.buffer().len(), 16);
+    /// ```
+    pub fn fill_placement(mut self, placement: FillPlacement) -> BufReader<R> {
+        self.placement = placement;
+        self
+    }
+
+    /// Sets how `peek` handles requests larger than the buffer, see `PeekPolicy`.
+    pub fn peek_policy(mut self, policy: PeekPolicy) -> BufReader<R> {
+        self.peek_policy = policy;
+        self
+    }
+
+    /// Sets how `read_char`, `peek_char` and `unread_char` handle invalid UTF-8, see `Utf8Policy`.
+    pub fn utf8_policy(mut self, policy: Utf8Policy) -> BufReader<R> {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Pins marked bytes in the buffer, see `mark`.
+    ///
+    /// While a mark is active, refills keep the bytes from the oldest mark onwards
+    /// and grow the buffer if required, until more than `read_limit` bytes have
+    /// been read past the mark. Within that limit `reset` never touches the
+    /// inner reader. Pinning has no effect in block cache mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let inner = Cursor::new((0..64).collect::<Vec<u8>>());
+    /// let mut reader = BufReader::with_capacity(4, inner).pin_marks(32);
+    ///
+    /// let mark = reader.mark();
+    /// let mut buf = [0; 10];
+    /// reader.read_exact(&mut buf).unwrap();
+    /// assert!(reader.capacity() >= 10);
+    ///
+    /// reader.reset(mark).unwrap();
+    /// assert_eq!(reader.buffer()[..4], [0, 1, 2, 3]);
+    /// ```
+    pub fn pin_marks(mut self, read_limit: usize) -> BufReader<R> {
+        self.mark_limit = Some(read_limit);
+        self
+    }
+
+    /// Tracks line numbers for `line_col` and `line_col_of`.
+    ///
+    /// Newlines are counted as bytes are consumed, plus 8 bytes per 1024 lines
+    /// for checkpoints. Tracking starts at position 0.
+    pub fn track_lines(mut self) -> BufReader<R> {
+        self.tracker = Some(LineTracker::new());
+        self
+    }
+
     /// Returns the absolute file pointer position.
     pub fn position(&self) -> u64 { self.absolute_pos }
 
-    /// Returns the total buffer capacity.
-    pub fn capacity(&self) -> usize { self.cap }
+    /// Returns the size of the internal buffer in bytes.
+    ///
+    /// This is the allocated size, it doesn't change on seeks or at EOF.
+    pub fn capacity(&self) -> usize { self.buf.len() }
+
+    /// Returns the number of valid bytes in the internal buffer, including consumed ones.
+    ///
+    /// This is 0 until the first read and after seeking outside of the buffer.
+    /// At EOF the last fill may leave it below `capacity()` or at 0.
+    pub fn filled(&self) -> usize { self.cap }
+
+    /// Returns the number of bytes requested from the underlying reader per fill.
+    ///
+    /// This equals `capacity()` unless adaptive sizing is enabled or `shrink_to_fit` was called.
+    pub fn fill_size(&self) -> usize { self.buf_size }
 
     /// Returns the current number of remaining bytes available in the buffer.
     pub fn available(&self) -> usize {
-        self.cap.checked_sub(self.buf_pos).unwrap_or(0)
+        self.cap.saturating_sub(self.buf_pos)
+    }
+
+    /// Returns the unconsumed bytes of the internal buffer without filling it.
+    ///
+    /// Unlike `fill_buf` this never invokes the underlying reader,
+    /// the slice is empty after seeking outside of the buffer and at EOF.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.buf_pos..self.cap]
+    }
+
+    /// Returns the length of the underlying stream.
+    ///
+    /// The length is queried from the underlying reader once and cached afterwards.
+    /// It is used to resolve `SeekFrom::End(_)` without invoking the underlying reader.
+    pub fn len(&mut self) -> io::Result<u64> {
+        if let Some(len) = self.len {
+            return Ok(len);
+        }
+        self.inner_pos = None;
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        self.inner_pos = Some(len);
+        self.len = Some(len);
+        Ok(len)
+    }
+
+    /// Returns `true` if the underlying stream is empty, see `len`.
+    pub fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Sets the cached stream length, e.g. if it is already known from file metadata.
+    pub fn set_len_hint(&mut self, len: u64) {
+        self.len = Some(len);
+    }
+
+    /// Discards the cached stream length.
+    ///
+    /// This should be called if the underlying stream may have grown or shrunk,
+    /// the next `SeekFrom::End(_)` queries the underlying reader again.
+    pub fn invalidate_len(&mut self) {
+        self.len = None;
+    }
+
+    /// Resizes the internal buffer to `new_cap` bytes.
+    ///
+    /// As many unconsumed bytes as fit are kept, remaining space is used to keep
+    /// already consumed bytes for seeking backwards. The position is not changed.
+    ///
+    /// In block cache mode this changes the block size and drops all cached pages.
+    ///
+    /// # Panics
+    ///
+    /// Panics in block cache mode if `new_cap` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::with_capacity(4, Cursor::new([0, 1, 2, 3, 4, 5]));
+    /// let mut buf = [0; 1];
+    /// reader.read(&mut buf).unwrap();
+    ///
+    /// reader.resize_buffer(2);
+    /// assert_eq!(reader.capacity(), 2);
+    /// assert_eq!(reader.buffer(), [1, 2]);
+    /// ```
+    pub fn resize_buffer(&mut self, new_cap: usize) {
+        self.buf_size = new_cap;
+        if let Some(ref mut cache) = self.cache {
+            // Pages are no longer aligned to the block size
+            cache.set_block_size(new_cap);
+            self.buf = vec![0; new_cap].into_boxed_slice();
+            self.buf_pos = 0;
+            self.cap = 0;
+            return;
+        }
+        let keep = self.available().min(new_cap);
+        let back = self.buf_pos.min(new_cap - keep);
+        let mut buf = vec![0; new_cap].into_boxed_slice();
+        buf[..back + keep].copy_from_slice(&self.buf[self.buf_pos - back..self.buf_pos + keep]);
+        self.buf = buf;
+        self.buf_pos = back;
+        self.cap = back + keep;
+    }
+
+    /// Shrinks the internal buffer to the unconsumed bytes and drops cached pages.
+    ///
+    /// The buffer grows back to `fill_size()` before it is filled the next time,
+    /// so this releases memory of long-lived readers which are currently idle.
+    pub fn shrink_to_fit(&mut self) {
+        if let Some(ref mut cache) = self.cache {
+            cache.clear();
+        }
+        let keep = self.available();
+        let mut buf = vec![0; keep].into_boxed_slice();
+        buf.copy_from_slice(&self.buf[self.buf_pos..self.cap]);
+        self.buf = buf;
+        self.buf_pos = 0;
+        self.cap = keep;
+    }
+
+    /// Returns the next `n` bytes as a contiguous slice without consuming them.
+    ///
+    /// Unconsumed bytes are moved to the front of the buffer if required and the buffer is
+    /// topped up from the underlying reader. The slice is only shorter than `n` at EOF.
+    /// The position is never changed.
+    ///
+    /// # Errors
+    ///
+    /// If `n` exceeds the buffer capacity an error of the kind `InvalidInput` is returned,
+    /// unless the buffer is allowed to grow with `PeekPolicy::Grow`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::with_capacity(4, Cursor::new([0, 1, 2, 3, 4, 5]));
+    /// let mut buf = [0; 3];
+    /// reader.read(&mut buf).unwrap();
+    ///
+    /// assert_eq!(reader.peek(3).unwrap(), [3, 4, 5]);
+    /// assert_eq!(reader.position(), 3);
+    /// ```
+    pub fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.available() < n {
+            let capacity = self.buf.len().max(self.buf_size);
+            if n > capacity {
+                match self.peek_policy {
+                    PeekPolicy::Error => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "peek exceeds the buffer capacity"));
+                    }
+                    // `fill_contiguous` detaches the page and grows the buffer
+                    PeekPolicy::Grow if self.cache.is_some() => {}
+                    PeekPolicy::Grow => self.resize_buffer(n),
+                }
+            }
+            self.fill_for_peek()?;
+            self.fill_contiguous(n)?;
+        }
+        let end = self.cap.min(self.buf_pos + n);
+        Ok(&self.buf[self.buf_pos..end])
+    }
+
+    /// Returns the hit and miss counters if block cache mode is enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats())
     }
 
     /// Consumes `self`, synchronizes the inner reader position and returns the inner reader.
     pub fn into_inner(mut self) -> io::Result<R> {
         // Sync position of internal reader
-        try!(self.inner.seek(SeekFrom::Start(self.absolute_pos)));
+        let pos = self.absolute_pos;
+        self.sync_inner(pos)?;
         Ok(self.inner)
     }
 
-    /// Syncs the position of our underlying reader and empties the buffer
-    fn sync_and_flush(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        self.buf_pos = self.cap;
-        self.absolute_pos = try!(self.inner.seek(pos));
-        Ok(self.absolute_pos)
+    /// Empties the buffer and moves to `pos`.
+    ///
+    /// Seeking the underlying reader is deferred until the next fill.
+    fn sync_and_flush(&mut self, pos: u64) -> io::Result<u64> {
+        self.check_history(SeekFrom::Start(pos))?;
+        let window_start = self.absolute_pos - self.buf_pos as u64;
+        self.buf_pos = 0;
+        self.cap = 0;
+        self.backward_from = if pos < window_start && self.placement != FillPlacement::Forward {
+            Some(window_start)
+        } else {
+            None
+        };
+        if let Some(ref mut adaptive) = self.adaptive {
+            adaptive.seeked();
+        }
+        self.absolute_pos = pos;
+        Ok(pos)
+    }
+
+    /// Seeks the underlying reader to `pos` unless it is already there
+    fn sync_inner(&mut self, pos: u64) -> io::Result<()> {
+        if self.inner_pos != Some(pos) {
+            self.inner_pos = None;
+            self.inner_pos = Some(self.inner.seek(SeekFrom::Start(pos))?);
+        }
+        Ok(())
+    }
+
+    /// Reads the window containing the current position into the buffer
+    fn fill_window(&mut self) -> io::Result<()> {
+        if let Some(ref mut adaptive) = self.adaptive {
+            self.buf_size = adaptive.next_size(self.buf_size);
+        }
+        if let Some(keep_from) = self.retain_start() {
+            // Grows the buffer itself, without losing the kept bytes
+            return self.refill_keeping(keep_from);
+        }
+        if self.buf.len() < self.buf_size {
+            self.buf = vec![0; self.buf_size].into_boxed_slice();
+        }
+        let pos = self.absolute_pos;
+        let size = self.buf_size as u64;
+        let start = match (self.backward_from.take(), self.placement) {
+            (Some(_), FillPlacement::Center) => pos.saturating_sub(size / 2),
+            // Far jumps get a window which ends right behind the target
+            (Some(end), FillPlacement::Backward) if end - pos > size => {
+                (pos + 1).saturating_sub(size)
+            }
+            (Some(end), FillPlacement::Backward) => end.saturating_sub(size),
+            _ => pos,
+        };
+        self.load_window(start)
+    }
+
+    /// Returns the first position which has to stay buffered on the next refill.
+    ///
+    /// This covers the history of non-seekable readers and the oldest pinned mark,
+    /// as long as no more than the mark limit has been read since.
+    fn retain_start(&self) -> Option<u64> {
+        let pos = self.absolute_pos;
+        let window_start = pos - self.buf_pos as u64;
+        let mut keep = pos.saturating_sub(self.history.unwrap_or(0) as u64);
+        if let (Some(limit), Some(&pin)) = (self.mark_limit, self.marks.iter().min()) {
+            if pin >= window_start && pin <= pos && pos - pin <= limit as u64 {
+                keep = keep.min(pin);
+            }
+        }
+        let keep = keep.max(window_start);
+        if keep < pos { Some(keep) } else { None }
+    }
+
+    /// Refills the exhausted buffer, keeping the bytes from `keep_from` onwards
+    fn refill_keeping(&mut self, keep_from: u64) -> io::Result<()> {
+        let kept = (self.absolute_pos - keep_from) as usize;
+        let needed = kept + self.buf_size;
+        if self.buf.len() < needed {
+            let mut buf = vec![0; needed].into_boxed_slice();
+            buf[..kept].copy_from_slice(&self.buf[self.buf_pos - kept..self.buf_pos]);
+            self.buf = buf;
+        } else {
+            self.buf.copy_within(self.buf_pos - kept..self.buf_pos, 0);
+        }
+        self.buf_pos = kept;
+        self.cap = kept;
+        self.backward_from = None;
+
+        let pos = self.absolute_pos;
+        self.sync_inner(pos)?;
+        let n = self.inner.read(&mut self.buf[kept..needed])?;
+        self.inner_pos = Some(pos + n as u64);
+        self.cap += n;
+        Ok(())
+    }
+
+    /// Reads the window starting at `start` into the buffer,
+    /// the window has to contain the current position unless EOF is reached before
+    fn load_window(&mut self, start: u64) -> io::Result<()> {
+        let pos = self.absolute_pos;
+        self.sync_inner(start)?;
+        if start == pos {
+            let n = self.inner.read(&mut self.buf[..self.buf_size])?;
+            self.inner_pos = Some(pos + n as u64);
+            self.cap = n;
+            self.buf_pos = 0;
+            return Ok(());
+        }
+
+        // The window has to reach the current position
+        self.cap = 0;
+        self.buf_pos = 0;
+        let offset = (pos - start) as usize;
+        let min = match self.read_mode {
+            ReadMode::Fill => self.buf_size,
+            ReadMode::Single => (offset + 1).min(self.buf_size),
+        };
+        let n = read_at_least(&mut self.inner, &mut self.buf[..self.buf_size], min)?;
+        self.inner_pos = Some(start + n as u64);
+        if offset <= n {
+            self.cap = n;
+            self.buf_pos = offset;
+        }
+        // Otherwise the position is behind the end of the stream
+        Ok(())
+    }
+
+    /// Prepares the buffer for `fill_contiguous`.
+    ///
+    /// The buffer is restored to the fill size and filled if it is exhausted.
+    fn fill_for_peek(&mut self) -> io::Result<()> {
+        if self.buf.len() < self.buf_size {
+            // Restore the buffer after `shrink_to_fit`
+            let size = self.buf_size;
+            self.resize_buffer(size);
+        }
+        if self.available() == 0 {
+            // A regular fill keeps block cache pages aligned
+            self.fill_buf()?;
+        }
+        Ok(())
+    }
+
+    /// Tops up the buffer until `n` bytes are available or EOF is reached.
+    ///
+    /// Unconsumed and pinned bytes are moved to the front of the buffer
+    /// if they don't fit otherwise.
+    /// `n` must not exceed the size of the buffer, unless pinned bytes are kept.
+    fn fill_contiguous(&mut self, n: usize) -> io::Result<()> {
+        if self.available() >= n {
+            return Ok(());
+        }
+        if let Some(ref mut cache) = self.cache {
+            // The buffer is about to stop holding an aligned page
+            cache.detach();
+        }
+        if self.buf.len() - self.buf_pos < n {
+            // Keep pinned bytes, growing the buffer if they don't fit otherwise
+            let keep = match self.retain_start() {
+                Some(keep_from) => self.buf_pos - (self.absolute_pos - keep_from) as usize,
+                None => self.buf_pos,
+            };
+            let kept = self.buf_pos - keep;
+            if self.buf.len() < kept + n {
+                let mut buf = vec![0; kept + n.max(self.buf_size)].into_boxed_slice();
+                buf[..self.cap - keep].copy_from_slice(&self.buf[keep..self.cap]);
+                self.buf = buf;
+            } else {
+                self.buf.copy_within(keep..self.cap, 0);
+            }
+            self.cap -= keep;
+            self.buf_pos -= keep;
+        }
+        self.backward_from = None;
+        let mut window_end = self.absolute_pos + self.available() as u64;
+        self.sync_inner(window_end)?;
+        while self.available() < n {
+            match self.inner.read(&mut self.buf[self.cap..]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.cap += read;
+                    window_end += read as u64;
+                    self.inner_pos = Some(window_end);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the buffered bytes before the current position.
+    ///
+    /// If there are none, a window ending at the current position is loaded.
+    /// The returned slice is only empty at the start of the stream.
+    fn fill_backward(&mut self) -> io::Result<&[u8]> {
+        let pos = self.absolute_pos;
+        if self.buf_pos == 0 && pos > 0 {
+            if self.cache.is_some() {
+                // Load the page containing the previous byte
+                self.absolute_pos = pos - 1;
+                self.cap = 0;
+                let result = self.fill_from_cache();
+                self.absolute_pos = pos;
+                result?;
+                if self.buf_pos < self.cap {
+                    self.buf_pos += 1;
+                } else {
+                    self.cap = 0;
+                    self.buf_pos = 0;
+                }
+            } else {
+                if self.buf.len() < self.buf_size {
+                    self.buf = vec![0; self.buf_size].into_boxed_slice();
+                }
+                self.backward_from = None;
+                let start = pos.saturating_sub(self.buf_size as u64);
+                self.load_window(start)?;
+            }
+        }
+        Ok(&self.buf[..self.buf_pos])
+    }
+
+    /// Moves to the end of the stream if the position is behind it.
+    /// Returns `true` if the position was changed.
+    ///
+    /// The cached length is only queried again if it lies before the position,
+    /// as the stream may have grown since, so a length hint is kept otherwise.
+    fn clamp_to_end(&mut self) -> io::Result<bool> {
+        let pos = self.absolute_pos;
+        if self.len()? < pos {
+            self.invalidate_len();
+        }
+        let len = self.len()?;
+        if len < pos {
+            self.seek(SeekFrom::Start(len))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Returns `true` if a read of `n` bytes should skip the internal buffer
+    fn bypass_buffer(&self, n: usize) -> bool {
+        self.buf_pos == self.cap && n >= self.buf_size && self.cache.is_none()
+            && (self.mark_limit.is_none() || self.marks.is_empty()) && self.history.is_none()
+    }
+
+    /// Reads from the inner reader into `buf`, the internal buffer has to be empty
+    fn read_direct(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.backward_from = None;
+        let pos = self.absolute_pos;
+        self.sync_inner(pos)?;
+        let n = self.inner.read(buf)?;
+        self.inner_pos = Some(pos + n as u64);
+        self.absolute_pos += n as u64;
+        self.buf_pos = 0;
+        self.cap = 0;
+        Ok(n)
+    }
+
+    /// Loads the page containing the current position into the buffer.
+    ///
+    /// With `ReadMode::Single` a page may be cached before it is complete,
+    /// it is topped up once a position behind its cached bytes is read.
+    fn fill_from_cache(&mut self) -> io::Result<()> {
+        let (block_size, cached) = {
+            let cache = self.cache.as_mut().unwrap();
+            let index = self.absolute_pos / cache.block_size() as u64;
+            (cache.block_size(), cache.fetch(index, &mut self.buf))
+        };
+        let index = self.absolute_pos / block_size as u64;
+        let offset = (self.absolute_pos % block_size as u64) as usize;
+        let len = match cached {
+            Some(len) if self.read_mode == ReadMode::Fill || offset < len || len == block_size => len,
+            cached => {
+                let filled = cached.unwrap_or(0);
+                self.cap = 0;
+                self.buf_pos = 0;
+                let start = index * block_size as u64 + filled as u64;
+                self.sync_inner(start)?;
+                let min = match self.read_mode {
+                    ReadMode::Fill => block_size - filled,
+                    ReadMode::Single => offset + 1 - filled,
+                };
+                let n = read_at_least(&mut self.inner, &mut self.buf[filled..], min)?;
+                self.inner_pos = Some(start + n as u64);
+                self.cache.as_mut().unwrap().loaded(index, filled + n);
+                filled + n
+            }
+        };
+        if offset < len {
+            self.cap = len;
+            self.buf_pos = offset;
+        } else {
+            // Behind the end of the stream
+            self.cap = 0;
+            self.buf_pos = 0;
+        }
+        Ok(())
     }
 
     /// Seeks `n` bytes backwards from current position
-    fn seek_backward(&mut self, n: i64) -> io::Result<u64> {
-        let n_abs = n.abs() as usize;
-        if self.buf_pos.checked_sub(n_abs).is_some() {
+    fn seek_backward(&mut self, n: u64) -> io::Result<u64> {
+        if n <= self.buf_pos as u64 {
             // Seek our internal buffer
-            self.absolute_pos -= n_abs as u64;
-            self.buf_pos -= n_abs;
+            self.absolute_pos -= n;
+            self.buf_pos -= n as usize;
             Ok(self.absolute_pos)
         } else {
             // Out of scope. Seek inner reader to new position and reset buffer
-            let new_pos = self.absolute_pos - n_abs as u64;
-            self.sync_and_flush(SeekFrom::Start(new_pos))
+            match self.absolute_pos.checked_sub(n) {
+                Some(new_pos) => self.sync_and_flush(new_pos),
+                None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "invalid seek to a negative position")),
+            }
         }
     }
 
     /// Seeks `n` bytes forwards from current position
-    fn seek_forward(&mut self, n: usize) -> io::Result<u64> {
-        if self.available().checked_sub(n).is_some() {
-            self.consume(n);
+    fn seek_forward(&mut self, n: u64) -> io::Result<u64> {
+        if n <= self.available() as u64 {
+            self.consume(n as usize);
             Ok(self.absolute_pos)
         } else {
             // Out of scope. Seek inner reader to new position and reset buffer
-            let new_pos = self.absolute_pos + n as u64;
-            self.sync_and_flush(SeekFrom::Start(new_pos))
+            match self.absolute_pos.checked_add(n) {
+                Some(new_pos) => self.sync_and_flush(new_pos),
+                None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "invalid seek to an overflowing position")),
+            }
+        }
+    }
+
+    /// Seeks to the absolute position `pos`.
+    /// The buffer is reused if `pos` lies anywhere within the buffered range.
+    fn seek_absolute(&mut self, pos: u64) -> io::Result<u64> {
+        match pos.checked_sub(self.absolute_pos) {
+            Some(n) => self.seek_forward(n),
+            None => self.seek_backward(self.absolute_pos - pos),
+        }
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> BufReader<ReadAhead<R>> {
+    /// Creates a new `BufReader` with the specified buffer capacity, which reads
+    /// the next window on a background thread while the current one is consumed.
+    ///
+    /// Seeks outside of the buffer redirect the background thread,
+    /// see `ReadAhead` for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use seek_bufread::BufReader;
+    ///
+    /// # fn foo() -> std::io::Result<()> {
+    /// let mut f = File::open("log.txt")?;
+    /// let mut reader = BufReader::with_read_ahead(1024 * 1024, f);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_read_ahead(cap: usize, inner: R) -> BufReader<ReadAhead<R>> {
+        BufReader::with_capacity(cap, ReadAhead::new(cap, inner))
+    }
+}
+
+/// Reads from `reader` into `buf` until at least `min` bytes are read or EOF is reached.
+fn read_at_least<R: Read>(reader: &mut R, buf: &mut [u8], min: usize) -> io::Result<usize> {
+    let mut n_total = 0;
+    while n_total < min {
+        match reader.read(&mut buf[n_total..]) {
+            Ok(0) => break,
+            Ok(n) => n_total += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
+    Ok(n_total)
 }
 
-impl<R: Read> Read for BufReader<R> {
+impl<R: Read + Seek> Read for BufReader<R> {
     /// Reads the next available bytes from buffer or inner stream.
     /// Doesn't guarantee the whole buffer is filled.
     /// Returns number of read bytes.
+    ///
+    /// With `ReadMode::Fill` this reads until `buf` is full or EOF is reached,
+    /// with `ReadMode::Single` the inner reader is invoked at most once.
+    ///
+    /// If the internal buffer is empty and at least as many bytes as its capacity
+    /// are requested, the inner reader reads directly into `buf`.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_mode == ReadMode::Single {
+            if self.bypass_buffer(buf.len()) {
+                return self.read_direct(buf);
+            }
+            let n_read = self.fill_buf()?.read(buf)?;
+            self.consume(n_read);
+            return Ok(n_read);
+        }
+
         let n_exp = buf.len();
         let mut n_total = 0;
         while n_total < n_exp {
-            let n_read = try!(try!(self.fill_buf()).read(&mut buf[n_total..]));
+            if self.bypass_buffer(n_exp - n_total) {
+                let n_read = self.read_direct(&mut buf[n_total..])?;
+                if n_read == 0 {
+                    break;
+                }
+                n_total += n_read;
+                continue;
+            }
+            let n_read = self.fill_buf()?.read(&mut buf[n_total..])?;
             if n_read == 0 {
                 break;
             }
@@ -186,18 +996,24 @@ impl<R: Read> Read for BufReader<R> {
     }
 }
 
-impl<R: Read> BufRead for BufReader<R> {
+impl<R: Read + Seek> BufRead for BufReader<R> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         // If we've reached the end of our internal buffer then we need to fetch
         // some more data from the underlying reader.
         if self.cap == self.buf_pos {
-            self.cap = try!(self.inner.read(&mut self.buf));
-            self.buf_pos = 0;
+            if self.cache.is_some() {
+                self.fill_from_cache()?;
+            } else {
+                self.fill_window()?;
+            }
         }
         Ok(&self.buf[self.buf_pos..self.cap])
     }
 
     fn consume(&mut self, amt: usize) {
+        if let Some(ref mut tracker) = self.tracker {
+            tracker.count(self.absolute_pos, &self.buf[self.buf_pos..self.buf_pos + amt]);
+        }
         self.buf_pos += amt;
         self.absolute_pos += amt as u64;
     }
@@ -213,23 +1029,25 @@ impl<R: Read + Seek> Seek for BufReader<R> {
     /// Calling `.unwrap()` immediately after a seek doesn't guarantee
     /// the underlying reader at the same position!
     ///
+    /// Seeks outside of the internal buffer are deferred until the next read,
+    /// so errors of the underlying reader may be reported by that read instead.
+    ///
     /// See `std::io::Seek` for more details.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         match pos {
-            SeekFrom::Current(n) => {
-                match n >= 0 {
-                    true => self.seek_forward(n as usize),
-                    false => self.seek_backward(n)
-                }
-            }
-            SeekFrom::Start(n) => {
-                // Check difference between actual and requested position
-                match n.checked_sub(self.absolute_pos) {
-                    Some(n_bytes) => self.seek_forward(n_bytes as usize),
-                    None => self.sync_and_flush(pos)
-                }
-            }
-            _ => self.sync_and_flush(pos)
+            SeekFrom::Current(n) if n >= 0 => self.seek_forward(n as u64),
+            SeekFrom::Current(n) => self.seek_backward(n.unsigned_abs()),
+            SeekFrom::Start(n) => self.seek_absolute(n),
+            SeekFrom::End(n) if n >= 0 => match self.len()?.checked_add(n as u64) {
+                Some(pos) => self.seek_absolute(pos),
+                None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "invalid seek to an overflowing position")),
+            },
+            SeekFrom::End(n) => match self.len()?.checked_sub(n.unsigned_abs()) {
+                Some(pos) => self.seek_absolute(pos),
+                None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "invalid seek to a negative position")),
+            },
         }
     }
 }
@@ -239,17 +1057,56 @@ impl<R> fmt::Debug for BufReader<R> where R: fmt::Debug + Read + Seek {
         fmt.debug_struct("BufReader")
             .field("reader", &self.inner)
             .field("available", &self.available())
-            .field("capacity", &self.cap)
+            .field("capacity", &self.capacity())
+            .field("filled", &self.cap)
             .field("position", &self.absolute_pos)
             .finish()
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unused_io_amount)]
 mod tests {
     use super::*;
     use std::io::{self, Cursor, Read, Seek, SeekFrom};
 
+    /// Counts the calls to the wrapped reader.
+    pub struct CountingReader<R> {
+        inner: R,
+        pub reads: usize,
+        pub seeks: usize,
+        pub fail_seeks: bool,
+        pub max_read: usize,
+    }
+
+    impl<R> CountingReader<R> {
+        pub fn new(inner: R) -> CountingReader<R> {
+            CountingReader { inner, reads: 0, seeks: 0, fail_seeks: false, max_read: usize::MAX }
+        }
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            let n = buf.len().min(self.max_read);
+            self.inner.read(&mut buf[..n])
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.seeks += 1;
+            if self.fail_seeks {
+                return Err(io::Error::other("seek failed"));
+            }
+            self.inner.seek(pos)
+        }
+    }
+
+    pub fn counting_cursor(len: usize) -> CountingReader<Cursor<Vec<u8>>> {
+        CountingReader::new(Cursor::new((0..len).map(|i| i as u8).collect()))
+    }
+
     #[test]
     fn default_behaviour() {
         let mut reader = BufReader::new(Cursor::new([5, 6, 7, 0, 1, 2, 3, 4]));
@@ -490,4 +1347,779 @@ mod tests {
         inner.read(&mut buf).unwrap();
         assert_eq!(buf, [13, 14, 15, 16, 0, 0, 0, 0]);
     }
+
+    #[test]
+    fn block_cache_hits() {
+        let mut reader = BufReader::with_block_cache(8, 4, counting_cursor(64));
+
+        reader.seek(SeekFrom::Start(42)).unwrap();
+        let mut buf = [0; 4];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [42, 43, 44, 45]);
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3, 4, 5]);
+
+        // Jump back into the first page
+        reader.seek(SeekFrom::Start(40)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [40, 41, 42, 43]);
+        reader.seek(SeekFrom::Start(6)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [6, 7, 8, 9]);
+
+        assert_eq!(reader.cache_stats(), Some(CacheStats { hits: 2, misses: 3 }));
+        // The miss of the page following the first one doesn't need a seek
+        assert_eq!(reader.inner.seeks, 2);
+        assert_eq!(reader.position(), 10);
+    }
+
+    #[test]
+    fn block_cache_eviction() {
+        let mut reader = BufReader::with_block_cache(4, 2, counting_cursor(16));
+
+        let mut buf = [0; 12];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(reader.cache_stats(), Some(CacheStats { hits: 0, misses: 3 }));
+
+        // Page 1 is still cached, page 0 has been evicted
+        reader.seek(SeekFrom::Start(5)).unwrap();
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [5, 6]);
+        assert_eq!(reader.cache_stats(), Some(CacheStats { hits: 1, misses: 3 }));
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1]);
+        assert_eq!(reader.cache_stats(), Some(CacheStats { hits: 1, misses: 4 }));
+    }
+
+    #[test]
+    fn block_cache_end() {
+        let inner = Cursor::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        let mut reader = BufReader::with_block_cache(4, 2, inner);
+
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [14, 15, 16, 0, 0, 0, 0, 0]);
+
+        reader.seek(SeekFrom::Start(30)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        reader.seek(SeekFrom::Current(-16)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [14, 15, 16, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn single_window_has_no_cache_stats() {
+        let reader = BufReader::new(Cursor::new([0, 1, 2]));
+        assert_eq!(reader.cache_stats(), None);
+    }
+
+    #[test]
+    fn seek_start_backward_in_buffer() {
+        let mut reader = BufReader::with_capacity(16, counting_cursor(32));
+
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut buf = [0; 4];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [10, 11, 12, 13]);
+
+        // Both targets are behind the current position but still buffered
+        reader.seek(SeekFrom::Start(11)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [11, 12, 13, 14]);
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [10, 11, 12, 13]);
+
+        assert_eq!(reader.inner.seeks, 1);
+        assert_eq!(reader.inner.reads, 1);
+    }
+
+    #[test]
+    fn seek_in_buffer_window_bounds() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(32));
+
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Current(6)).unwrap(), 8);
+        assert_eq!(reader.seek(SeekFrom::Start(0)).unwrap(), 0);
+        assert_eq!(reader.seek(SeekFrom::Current(7)).unwrap(), 7);
+        assert_eq!(reader.seek(SeekFrom::Current(-7)).unwrap(), 0);
+        assert_eq!(reader.inner.seeks, 0);
+        assert_eq!(reader.inner.reads, 1);
+
+        // One byte behind the window
+        assert_eq!(reader.seek(SeekFrom::Start(9)).unwrap(), 9);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [9, 10]);
+        assert_eq!(reader.inner.seeks, 1);
+    }
+
+    #[test]
+    fn seek_after_flush() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        let mut buf = [0; 4];
+        reader.read(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        reader.seek(SeekFrom::Current(-2)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [18, 19, 20, 21]);
+    }
+
+    #[test]
+    fn seek_negative() {
+        let mut reader = BufReader::new(Cursor::new([0, 1, 2, 3]));
+
+        let err = reader.seek(SeekFrom::Current(-1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn seek_end_cached_len() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(32));
+
+        assert_eq!(reader.seek(SeekFrom::End(-8)).unwrap(), 24);
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [24, 25]);
+        // Querying the length and moving back to the window
+        assert_eq!(reader.inner.seeks, 2);
+
+        // Resolved against the cached length and served from the buffer
+        assert_eq!(reader.seek(SeekFrom::End(-7)).unwrap(), 25);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [25, 26]);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 32);
+        assert_eq!(reader.inner.seeks, 2);
+        assert_eq!(reader.inner.reads, 1);
+        assert_eq!(reader.len().unwrap(), 32);
+    }
+
+    #[test]
+    fn seek_end_into_buffer() {
+        let mut reader = BufReader::with_capacity(16, counting_cursor(20));
+
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        // The first seek relative to the end still reuses the buffer
+        assert_eq!(reader.seek(SeekFrom::End(-12)).unwrap(), 8);
+        assert_eq!(reader.buffer().len(), 8);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [8, 9]);
+        assert_eq!(reader.inner.seeks, 1);
+        assert_eq!(reader.inner.reads, 1);
+    }
+
+    #[test]
+    fn len_keeps_position() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.len().unwrap(), 32);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5]);
+        assert_eq!(reader.position(), 6);
+    }
+
+    #[test]
+    fn len_hint_and_invalidate() {
+        let mut reader = BufReader::new(counting_cursor(32));
+
+        reader.set_len_hint(16);
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), 12);
+        assert!(reader.seek(SeekFrom::End(-17)).is_err());
+        assert_eq!(reader.inner.seeks, 0);
+
+        reader.invalidate_len();
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), 28);
+        assert_eq!(reader.len().unwrap(), 32);
+        assert_eq!(reader.inner.seeks, 1);
+    }
+
+    #[test]
+    fn lazy_seek() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(32));
+
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        reader.seek(SeekFrom::Current(-8)).unwrap();
+        reader.seek(SeekFrom::Start(16)).unwrap();
+        assert_eq!(reader.position(), 16);
+        assert_eq!(reader.inner.seeks, 0);
+
+        let mut buf = [0; 6];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [16, 17, 18, 19, 20, 21]);
+        assert_eq!(reader.inner.seeks, 1);
+
+        // Seeking back to the position of the inner reader is free
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        reader.seek(SeekFrom::Start(24)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [24, 25, 26, 27, 28, 29]);
+        assert_eq!(reader.inner.seeks, 1);
+    }
+
+    #[test]
+    fn lazy_seek_error() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+        reader.inner.fail_seeks = true;
+
+        reader.seek(SeekFrom::Start(8)).unwrap();
+        let mut buf = [0; 2];
+        assert!(reader.read(&mut buf).is_err());
+        assert_eq!(reader.position(), 8);
+
+        reader.inner.fail_seeks = false;
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [8, 9]);
+    }
+
+    #[test]
+    fn lazy_seek_into_inner() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        reader.seek(SeekFrom::Start(8)).unwrap();
+        let mut inner = reader.into_inner().unwrap();
+        let mut buf = [0; 2];
+        inner.read(&mut buf).unwrap();
+        assert_eq!(buf, [8, 9]);
+    }
+
+    #[test]
+    fn read_direct() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.inner.reads, 1);
+
+        // Drains the buffer first, then reads the remainder directly
+        let mut buf = [0; 10];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.position(), 12);
+        assert_eq!(reader.available(), 0);
+
+        // Smaller reads are buffered again
+        let mut buf = [0; 3];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [12, 13, 14]);
+        assert_eq!(reader.inner.reads, 3);
+        reader.seek(SeekFrom::Current(-3)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [12, 13, 14]);
+        assert_eq!(reader.inner.reads, 3);
+    }
+
+    #[test]
+    fn read_direct_after_seek() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        reader.seek(SeekFrom::End(-6)).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 6);
+        assert_eq!(buf, [26, 27, 28, 29, 30, 31, 0, 0]);
+        assert_eq!(reader.position(), 32);
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(reader.position(), 10);
+    }
+
+    #[test]
+    fn read_mode_fill() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        let mut buf = [0; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [3, 4, 5]);
+        assert_eq!(reader.inner.reads, 2);
+    }
+
+    #[test]
+    fn read_mode_single() {
+        let inner = counting_cursor(32);
+        let mut reader = BufReader::with_capacity(4, inner).read_mode(ReadMode::Single);
+
+        let mut buf = [0; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [0, 1, 2]);
+        // Only the remaining buffered byte is returned
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 3);
+        assert_eq!(reader.inner.reads, 1);
+
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 8);
+        assert_eq!(buf, [4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.position(), 12);
+    }
+
+    #[test]
+    fn read_mode_single_block_cache() {
+        let inner = counting_cursor(20);
+        let mut reader = BufReader::with_block_cache(16, 2, inner).read_mode(ReadMode::Single);
+
+        // The last page is short, but not read twice
+        reader.seek(SeekFrom::Start(18)).unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [18, 19]);
+        assert_eq!(reader.inner.reads, 1);
+    }
+
+    #[test]
+    fn read_mode_single_short_page() {
+        let mut inner = counting_cursor(20);
+        inner.max_read = 3;
+        let mut reader = BufReader::with_block_cache(8, 2, inner).read_mode(ReadMode::Single);
+
+        // Reads until the position is reached, the page stays incomplete
+        reader.seek(SeekFrom::Start(5)).unwrap();
+        let mut buf = [0; 1];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [5]);
+        assert_eq!(reader.inner.reads, 2);
+
+        // The page is topped up instead of ending the stream
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [6]);
+        assert_eq!(reader.inner.reads, 3);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, (7..20).collect::<Vec<u8>>());
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [0]);
+    }
+
+    #[test]
+    fn read_mode_single_placement() {
+        let inner = counting_cursor(20);
+        let mut reader = BufReader::with_capacity(8, inner)
+            .read_mode(ReadMode::Single)
+            .fill_placement(FillPlacement::Center);
+
+        let mut buf = [0; 1];
+        reader.seek(SeekFrom::Start(19)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.inner.reads, 1);
+
+        // The window 13..21 is centered on the target and ends behind the stream
+        reader.seek(SeekFrom::Start(17)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [17]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.buffer(), [18, 19]);
+    }
+
+    #[test]
+    fn read_mode_single_std() {
+        let inner = Cursor::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        let mut reader = io::BufReader::with_capacity(4, inner);
+
+        let mut buf = [0; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [0, 1, 2]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 3);
+    }
+
+    #[test]
+    fn capacity_and_filled() {
+        let inner = Cursor::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let mut reader = BufReader::with_capacity(4, inner);
+        assert_eq!(reader.capacity(), 4);
+        assert_eq!(reader.filled(), 0);
+        assert_eq!(reader.buffer(), []);
+
+        let mut buf = [0; 1];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.filled(), 4);
+        assert_eq!(reader.buffer(), [1, 2, 3]);
+
+        // Seeks inside the buffer keep it
+        reader.seek(SeekFrom::Current(2)).unwrap();
+        assert_eq!(reader.filled(), 4);
+        assert_eq!(reader.buffer(), [3]);
+
+        // Seeks outside of the buffer empty it
+        reader.seek(SeekFrom::Start(8)).unwrap();
+        assert_eq!(reader.capacity(), 4);
+        assert_eq!(reader.filled(), 0);
+        assert_eq!(reader.buffer(), []);
+
+        // Short fill at the end of the stream
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.filled(), 2);
+        assert_eq!(reader.buffer(), [9]);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reader.capacity(), 4);
+        assert_eq!(reader.filled(), 0);
+        assert_eq!(reader.buffer(), []);
+    }
+
+    #[test]
+    fn resize_buffer_grow() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        reader.resize_buffer(16);
+        assert_eq!(reader.capacity(), 16);
+        assert_eq!(reader.buffer(), [2, 3]);
+        assert_eq!(reader.position(), 2);
+
+        // Consumed bytes are kept as well
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = [0; 8];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(reader.inner.seeks, 0);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.filled(), 16);
+    }
+
+    #[test]
+    fn resize_buffer_shrink() {
+        let mut reader = BufReader::with_capacity(16, counting_cursor(32));
+
+        let mut buf = [0; 4];
+        reader.read(&mut buf).unwrap();
+        reader.resize_buffer(8);
+        assert_eq!(reader.capacity(), 8);
+        assert_eq!(reader.buffer(), [4, 5, 6, 7, 8, 9, 10, 11]);
+
+        // Dropped bytes are read again from the inner reader
+        let mut buf = [0; 10];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        assert_eq!(reader.position(), 14);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(32));
+
+        let mut buf = [0; 6];
+        reader.read(&mut buf).unwrap();
+        reader.shrink_to_fit();
+        assert_eq!(reader.capacity(), 2);
+        assert_eq!(reader.buffer(), [6, 7]);
+
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [6, 7, 8, 9, 10, 11]);
+        assert_eq!(reader.capacity(), 8);
+        assert_eq!(reader.position(), 12);
+    }
+
+    #[test]
+    fn resize_buffer_block_cache() {
+        let mut reader = BufReader::with_block_cache(4, 2, counting_cursor(32));
+
+        let mut buf = [0; 6];
+        reader.read(&mut buf).unwrap();
+        reader.resize_buffer(8);
+        assert_eq!(reader.position(), 6);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [6, 7, 8, 9, 10, 11]);
+        assert_eq!(reader.filled(), 8);
+
+        reader.shrink_to_fit();
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(reader.capacity(), 8);
+    }
+
+    #[test]
+    fn adaptive_grow() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(128)).adaptive(4, 16);
+
+        let mut buf = [0; 1];
+        let mut sizes = Vec::new();
+        while reader.read(&mut buf).unwrap() > 0 {
+            if reader.available() + 1 == reader.filled() {
+                sizes.push(reader.filled());
+            }
+        }
+        assert_eq!(sizes, [4, 8, 8, 16, 16, 16, 16, 16, 16, 12]);
+        assert_eq!(reader.fill_size(), 16);
+        assert_eq!(reader.capacity(), 16);
+    }
+
+    #[test]
+    fn adaptive_shrink() {
+        let mut reader = BufReader::with_capacity(16, counting_cursor(128)).adaptive(2, 16);
+
+        let mut buf = [0; 1];
+        for &pos in &[100, 10, 60, 30] {
+            reader.seek(SeekFrom::Start(pos)).unwrap();
+            reader.read(&mut buf).unwrap();
+            assert_eq!(buf, [pos as u8]);
+        }
+        assert_eq!(reader.fill_size(), 2);
+        assert_eq!(reader.filled(), 2);
+        // The allocation is kept
+        assert_eq!(reader.capacity(), 16);
+
+        // Seeks inside the buffer don't count
+        reader.seek(SeekFrom::Current(-1)).unwrap();
+        for _ in 0..5 {
+            reader.read(&mut buf).unwrap();
+        }
+        assert_eq!(buf, [34]);
+        assert_eq!(reader.fill_size(), 4);
+    }
+
+    #[test]
+    fn adaptive_clamps_initial_size() {
+        let reader = BufReader::with_capacity(4, Cursor::new([0; 8])).adaptive(8, 64);
+        assert_eq!(reader.fill_size(), 8);
+    }
+
+    #[test]
+    fn read_ahead() {
+        let inner = Cursor::new((0..100).collect::<Vec<u8>>());
+        let mut reader = BufReader::with_read_ahead(8, inner);
+
+        let mut buf = [0; 4];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3]);
+
+        reader.seek(SeekFrom::Start(50)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [50, 51, 52, 53]);
+
+        reader.seek(SeekFrom::Current(-10)).unwrap();
+        let mut buf = [0; 12];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55]);
+
+        reader.seek(SeekFrom::End(-2)).unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, [98, 99]);
+
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        let mut inner = reader.into_inner().unwrap().into_inner().unwrap();
+        let mut buf = [0; 2];
+        inner.read(&mut buf).unwrap();
+        assert_eq!(buf, [20, 21]);
+    }
+
+    fn step_backward<R: Read + Seek>(reader: &mut BufReader<R>, step: usize) -> Vec<u8> {
+        let mut buf = vec![0; step];
+        let mut out = Vec::new();
+        while reader.position() > 0 {
+            reader.seek(SeekFrom::Current(-(step as i64))).unwrap();
+            reader.read(&mut buf).unwrap();
+            out.extend(buf.iter().rev());
+            reader.seek(SeekFrom::Current(-(step as i64))).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn fill_placement_backward() {
+        let inner = counting_cursor(64);
+        let mut reader = BufReader::with_capacity(16, inner)
+            .fill_placement(FillPlacement::Backward);
+
+        reader.seek(SeekFrom::End(0)).unwrap();
+        let out = step_backward(&mut reader, 4);
+        assert_eq!(out, (0..64).rev().collect::<Vec<u8>>());
+        assert_eq!(reader.inner.reads, 4);
+        assert_eq!(reader.inner.seeks, 5);
+    }
+
+    #[test]
+    fn fill_placement_backward_far_jump() {
+        let inner = counting_cursor(256);
+        let mut reader = BufReader::with_capacity(16, inner)
+            .fill_placement(FillPlacement::Backward);
+
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        let mut buf = [0; 1];
+        reader.read(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [100]);
+        assert_eq!(reader.buffer(), []);
+
+        // The window ends behind the target, so stepping back stays in the buffer
+        let reads = reader.inner.reads;
+        assert_eq!(reader.fill_backward().unwrap(), &(85..101).collect::<Vec<u8>>()[..]);
+        reader.seek(SeekFrom::Current(-16)).unwrap();
+        assert_eq!(reader.inner.reads, reads);
+    }
+
+    #[test]
+    fn fill_placement_forward() {
+        let inner = counting_cursor(64);
+        let mut reader = BufReader::with_capacity(16, inner);
+
+        reader.seek(SeekFrom::End(0)).unwrap();
+        let out = step_backward(&mut reader, 4);
+        assert_eq!(out, (0..64).rev().collect::<Vec<u8>>());
+        assert_eq!(reader.inner.reads, 16);
+    }
+
+    #[test]
+    fn fill_placement_center() {
+        let inner = counting_cursor(64);
+        let mut reader = BufReader::with_capacity(16, inner)
+            .fill_placement(FillPlacement::Center);
+
+        reader.seek(SeekFrom::Start(40)).unwrap();
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [20, 21]);
+        assert_eq!(reader.buffer(), &(22..28).collect::<Vec<u8>>()[..]);
+        reader.seek(SeekFrom::Start(12)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [12, 13]);
+        assert_eq!(reader.inner.reads, 2);
+
+        // Near the start of the stream
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [3, 4]);
+        assert_eq!(reader.filled(), 16);
+        assert_eq!(reader.available(), 11);
+    }
+
+    #[test]
+    fn rev_lines_reuse_buffer() {
+        let inner = CountingReader::new(Cursor::new("a\nb\nc\n"));
+        let mut reader = BufReader::with_capacity(64, inner);
+
+        let lines: Vec<String> = reader.rev_lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, ["c", "b", "a"]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn peek() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(32));
+
+        let mut buf = [0; 7];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.peek(4).unwrap(), [7, 8, 9, 10]);
+        assert_eq!(reader.position(), 7);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.inner.seeks, 0);
+
+        // Served from the buffer
+        assert_eq!(reader.peek(8).unwrap(), [7, 8, 9, 10, 11, 12, 13, 14]);
+        assert_eq!(reader.inner.reads, 2);
+
+        let mut buf = [0; 9];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn peek_eof() {
+        let mut reader = BufReader::with_capacity(8, counting_cursor(10));
+
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        assert_eq!(reader.peek(4).unwrap(), [7, 8, 9]);
+        assert_eq!(reader.peek(4).unwrap(), [7, 8, 9]);
+        assert_eq!(reader.position(), 7);
+    }
+
+    #[test]
+    fn peek_policy() {
+        let mut reader = BufReader::with_capacity(4, counting_cursor(32));
+        let err = reader.peek(5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let inner = counting_cursor(32);
+        let mut reader = BufReader::with_capacity(4, inner).peek_policy(PeekPolicy::Grow);
+        let mut buf = [0; 2];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.peek(6).unwrap(), [2, 3, 4, 5, 6, 7]);
+        assert_eq!(reader.capacity(), 6);
+        assert_eq!(reader.position(), 2);
+    }
+
+    #[test]
+    fn peek_block_cache() {
+        let mut reader = BufReader::with_block_cache(4, 2, counting_cursor(32));
+
+        let mut buf = [0; 3];
+        reader.read(&mut buf).unwrap();
+        assert_eq!(reader.peek(4).unwrap(), [3, 4, 5, 6]);
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [3, 4, 5]);
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        reader.read(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+    }
+
+    #[test]
+    fn peek_grow_block_cache() {
+        let inner = counting_cursor(64);
+        let mut reader = BufReader::with_block_cache(4, 4, inner).peek_policy(PeekPolicy::Grow);
+
+        let mut buf = [0; 4];
+        reader.seek(SeekFrom::Start(40)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        assert_eq!(reader.peek(10).unwrap(), (2..12).collect::<Vec<u8>>());
+        assert_eq!(reader.position(), 2);
+
+        // The block size and the cached pages are kept
+        let reads = reader.inner.reads;
+        reader.seek(SeekFrom::Start(41)).unwrap();
+        reader.read_exact(&mut buf[..2]).unwrap();
+        assert_eq!(buf[..2], [41, 42]);
+        assert_eq!(reader.inner.reads, reads);
+        assert_eq!(reader.capacity(), 4);
+        reader.seek(SeekFrom::Start(6)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn peek_block_cache_after_seek() {
+        let mut reader = BufReader::with_block_cache(8, 2, counting_cursor(64));
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf[..1]).unwrap();
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        assert_eq!(reader.peek(2).unwrap(), [20, 21]);
+        // Straddles a page boundary
+        reader.seek(SeekFrom::Start(22)).unwrap();
+        assert_eq!(reader.peek(4).unwrap(), [22, 23, 24, 25]);
+
+        // Pages have to hold their own bytes afterwards
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3]);
+        reader.seek(SeekFrom::Start(16)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [16, 17, 18, 19]);
+    }
 }
@@ -0,0 +1,375 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Sparse line offset index for random access to lines.
+//!
+//! The index stores the offset of every `interval`-th line, `seek_to_line` seeks
+//! to the preceding checkpoint and scans forward over the remaining lines.
+//! Serialized indexes carry the stream length and a fingerprint of sampled
+//! blocks, to detect sources which changed since the index was built.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use search::memchr;
+use BufReader;
+
+/// Identifies serialized line indexes, the last byte is the format version.
+const MAGIC: &[u8; 8] = b"SBLINES1";
+
+/// Number of bytes hashed at the start, middle and end of the stream.
+const SAMPLE: u64 = 4096;
+
+/// A sparse index of line start offsets, created by `LineIndex::build`.
+///
+/// Lines are numbered from 0 and terminated by `\n`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineIndex {
+    interval: u64,       // number of lines between checkpoints
+    lines: u64,          // number of lines in the stream
+    terminated: bool,    // whether the stream is empty or ends with a newline
+    len: u64,            // length of the indexed stream
+    fingerprint: u64,    // hash of sampled blocks of the stream
+    offsets: Vec<u64>,   // start offset of every `interval`-th line
+}
+
+impl LineIndex {
+    /// Builds an index by reading the whole stream once, keeping a checkpoint every
+    /// `interval` lines.
+    ///
+    /// Smaller intervals make `seek_to_line` faster at the cost of 8 bytes per
+    /// checkpoint. The position of `reader` is restored afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{BufRead, Cursor};
+    /// use seek_bufread::{BufReader, LineIndex};
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("zero\none\ntwo\nthree\n"));
+    /// let index = LineIndex::build(&mut reader, 2).unwrap();
+    /// assert_eq!(index.lines(), 4);
+    ///
+    /// reader.seek_to_line(&index, 3).unwrap();
+    /// let mut line = String::new();
+    /// reader.read_line(&mut line).unwrap();
+    /// assert_eq!(line, "three\n");
+    /// ```
+    pub fn build<R: Read + Seek>(reader: &mut BufReader<R>, interval: usize)
+                                 -> io::Result<LineIndex> {
+        assert!(interval > 0, "line index interval must be non-zero");
+        let interval = interval as u64;
+        let saved = reader.position();
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut offsets = vec![0];
+        let mut newlines = 0u64;
+        let mut terminated = true;
+        loop {
+            let pos = reader.position();
+            let n = {
+                let buf = reader.fill_buf()?;
+                if buf.is_empty() {
+                    break;
+                }
+                let mut i = 0;
+                while let Some(j) = memchr(b'\n', &buf[i..]) {
+                    i += j + 1;
+                    newlines += 1;
+                    if newlines.is_multiple_of(interval) {
+                        offsets.push(pos + i as u64);
+                    }
+                }
+                terminated = buf[buf.len() - 1] == b'\n';
+                buf.len()
+            };
+            reader.consume(n);
+        }
+        let len = reader.position();
+        let index = LineIndex {
+            interval,
+            lines: if terminated { newlines } else { newlines + 1 },
+            terminated,
+            len,
+            fingerprint: fingerprint(reader, len)?,
+            offsets,
+        };
+        reader.seek(SeekFrom::Start(saved))?;
+        Ok(index)
+    }
+
+    /// Returns the number of lines in the indexed stream.
+    ///
+    /// A trailing newline doesn't start another line.
+    pub fn lines(&self) -> u64 { self.lines }
+
+    /// Returns the number of lines between checkpoints.
+    pub fn interval(&self) -> u64 { self.interval }
+
+    /// Returns `true` if the stream of `reader` still matches this index.
+    ///
+    /// Compares the length and a hash of up to 4 KiB at the start, middle and end of
+    /// the stream. Changes which keep the length and leave these blocks untouched are
+    /// not detected, `seek_to_line` reports some of them as `InvalidData` errors.
+    /// The position of `reader` is restored afterwards.
+    pub fn is_current<R: Read + Seek>(&self, reader: &mut BufReader<R>) -> io::Result<bool> {
+        let saved = reader.position();
+        reader.invalidate_len();
+        let len = reader.len()?;
+        let current = len == self.len && fingerprint(reader, len)? == self.fingerprint;
+        reader.seek(SeekFrom::Start(saved))?;
+        Ok(current)
+    }
+
+    /// Serializes the index, e.g. to a sidecar file next to the indexed one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use seek_bufread::{BufReader, LineIndex};
+    ///
+    /// # fn foo() -> std::io::Result<()> {
+    /// let mut reader = BufReader::new(File::open("data.csv")?);
+    /// let index = LineIndex::build(&mut reader, 1024)?;
+    /// index.write_to(&mut File::create("data.csv.idx")?)?;
+    ///
+    /// let index = LineIndex::read_from(&mut File::open("data.csv.idx")?)?;
+    /// if index.is_current(&mut reader)? {
+    ///     reader.seek_to_line(&index, 1234567)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        // The newline count determines the number of checkpoints
+        let newlines = if self.terminated { self.lines } else { self.lines - 1 };
+        let header = [self.interval, newlines, self.terminated as u64, self.len,
+                      self.fingerprint, self.offsets.len() as u64];
+        for value in header.iter().chain(self.offsets.iter()) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes an index written by `write_to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidData` if the data is not a line index.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<LineIndex> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a line index"));
+        }
+        let interval = read_u64(reader)?;
+        let newlines = read_u64(reader)?;
+        let terminated = read_u64(reader)?;
+        let len = read_u64(reader)?;
+        let fingerprint = read_u64(reader)?;
+        let count = read_u64(reader)?;
+        if interval == 0 || terminated > 1 || count != newlines / interval + 1 {
+            return Err(invalid_data("corrupt line index header"));
+        }
+        let terminated = terminated == 1;
+        let lines = if terminated { newlines } else { newlines + 1 };
+        let mut offsets = Vec::new();
+        for _ in 0..count {
+            let offset = read_u64(reader)?;
+            if offset > len || offsets.last().is_some_and(|&last| offset <= last) {
+                return Err(invalid_data("corrupt line index offsets"));
+            }
+            offsets.push(offset);
+        }
+        Ok(LineIndex { interval, lines, terminated, len, fingerprint, offsets })
+    }
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Moves to the start of line `n`, counted from 0, using `index`.
+    ///
+    /// Seeks to the closest preceding checkpoint and scans forward from there.
+    /// Returns the new position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidInput` if `n` is not less than `index.lines()`,
+    /// or `InvalidData` if the stream no longer matches the index.
+    pub fn seek_to_line(&mut self, index: &LineIndex, n: u64) -> io::Result<u64> {
+        if n >= index.lines {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "line number out of range"));
+        }
+        let checkpoint = index.offsets[(n / index.interval) as usize];
+        if checkpoint > 0 {
+            self.seek(SeekFrom::Start(checkpoint - 1))?;
+            if self.read_u8()? != b'\n' {
+                return Err(invalid_data("stream changed since the line index was built"));
+            }
+        } else {
+            self.seek(SeekFrom::Start(0))?;
+        }
+
+        let mut remaining = n % index.interval;
+        while remaining > 0 {
+            let n = {
+                let buf = self.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(invalid_data("stream changed since the line index was built"));
+                }
+                let mut i = 0;
+                while remaining > 0 {
+                    match memchr(b'\n', &buf[i..]) {
+                        Some(j) => {
+                            i += j + 1;
+                            remaining -= 1;
+                        }
+                        None => {
+                            i = buf.len();
+                            break;
+                        }
+                    }
+                }
+                i
+            };
+            self.consume(n);
+        }
+        Ok(self.position())
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Hashes blocks at the start, middle and end of a stream of length `len` with FNV-1a.
+fn fingerprint<R: Read + Seek>(reader: &mut BufReader<R>, len: u64) -> io::Result<u64> {
+    let sample = SAMPLE.min(len);
+    let mut hash = 0xcbf29ce484222325u64;
+    let mut block = vec![0; sample as usize];
+    for &start in &[0, (len - sample) / 2, len - sample] {
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut block)?;
+        for &b in &block {
+            hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn text(lines: usize) -> String {
+        (0..lines).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    fn line_at(reader: &mut BufReader<Cursor<Vec<u8>>>, index: &LineIndex, n: u64) -> String {
+        reader.seek_to_line(index, n).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn seek_to_line() {
+        let data = text(100);
+        for &(cap, interval) in &[(3, 1), (8, 7), (64, 10), (8192, 1000)] {
+            let mut reader = BufReader::with_capacity(cap, Cursor::new(data.clone().into_bytes()));
+            let index = LineIndex::build(&mut reader, interval).unwrap();
+            assert_eq!(index.lines(), 100);
+            assert_eq!(reader.position(), 0);
+            for &n in &[0, 1, 6, 7, 50, 99, 42, 3] {
+                assert_eq!(line_at(&mut reader, &index, n), format!("line {}\n", n));
+            }
+            let err = reader.seek_to_line(&index, 100).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn unterminated_last_line() {
+        let mut reader = BufReader::new(Cursor::new(b"a\n\nb".to_vec()));
+        let index = LineIndex::build(&mut reader, 2).unwrap();
+        assert_eq!(index.lines(), 3);
+        assert_eq!(line_at(&mut reader, &index, 1), "\n");
+        assert_eq!(line_at(&mut reader, &index, 2), "b");
+
+        let mut reader = BufReader::new(Cursor::new(Vec::new()));
+        assert_eq!(LineIndex::build(&mut reader, 2).unwrap().lines(), 0);
+    }
+
+    #[test]
+    fn serialize() {
+        let mut reader = BufReader::new(Cursor::new(text(50).into_bytes()));
+        let index = LineIndex::build(&mut reader, 8).unwrap();
+
+        let mut sidecar = Vec::new();
+        index.write_to(&mut sidecar).unwrap();
+        assert_eq!(LineIndex::read_from(&mut &sidecar[..]).unwrap(), index);
+
+        sidecar[0] = b'x';
+        let err = LineIndex::read_from(&mut &sidecar[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn serialize_unterminated() {
+        for data in &["a\nb", "a\nb\nc", "a", ""] {
+            let mut reader = BufReader::new(Cursor::new(data.as_bytes().to_vec()));
+            let index = LineIndex::build(&mut reader, 2).unwrap();
+
+            let mut sidecar = Vec::new();
+            index.write_to(&mut sidecar).unwrap();
+            let read = LineIndex::read_from(&mut &sidecar[..]).unwrap();
+            assert_eq!(read, index);
+            if index.lines() > 0 {
+                let last = index.lines() - 1;
+                assert_eq!(line_at(&mut reader, &read, last), line_at(&mut reader, &index, last));
+            }
+        }
+    }
+
+    #[test]
+    fn detect_changes() {
+        let data = text(2000).into_bytes();
+        let mut reader = BufReader::new(Cursor::new(data.clone()));
+        let index = LineIndex::build(&mut reader, 16).unwrap();
+        assert!(index.is_current(&mut reader).unwrap());
+
+        let mut appended = data.clone();
+        appended.extend_from_slice(b"more\n");
+        assert!(!index.is_current(&mut BufReader::new(Cursor::new(appended))).unwrap());
+
+        let mut edited = data.clone();
+        edited[10] = b'X';
+        assert!(!index.is_current(&mut BufReader::new(Cursor::new(edited))).unwrap());
+
+        // Outside of the sampled blocks, caught by the checkpoint check
+        let checkpoint = index.offsets[32] as usize;
+        assert!(checkpoint > SAMPLE as usize && checkpoint < data.len() / 2 - SAMPLE as usize);
+        let mut joined = data;
+        joined[checkpoint - 1] = b' ';
+        let mut reader = BufReader::new(Cursor::new(joined));
+        assert!(index.is_current(&mut reader).unwrap());
+        let err = reader.seek_to_line(&index, 513).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Reading lines from the end of a stream towards its start.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use BufReader;
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Reads the line which ends at the current position and appends it to `buf`.
+    ///
+    /// The line includes its terminator (`\n` or `\r\n`) if there is one.
+    /// Afterwards the position is at the start of the line, so repeated calls
+    /// step backwards through the stream. Returns the number of bytes read,
+    /// which is 0 at the start of the stream. A position behind the end of
+    /// the stream is moved to its end first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidData` if the line is not valid UTF-8,
+    /// the position is moved to the start of the line nevertheless.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Seek, SeekFrom};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("first\nsecond\n"));
+    /// reader.seek(SeekFrom::End(0)).unwrap();
+    ///
+    /// let mut line = String::new();
+    /// reader.read_line_backward(&mut line).unwrap();
+    /// assert_eq!(line, "second\n");
+    /// assert_eq!(reader.position(), 6);
+    /// ```
+    pub fn read_line_backward(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut segments = Vec::new();
+        let mut first = true;
+        loop {
+            let (n, found) = {
+                let available = self.fill_backward()?;
+                if available.is_empty() {
+                    // Behind the end of the stream, start at its end instead
+                    if first && self.absolute_pos > 0 && self.clamp_to_end()? {
+                        continue;
+                    }
+                    break;
+                }
+                // A newline directly before the position terminates this line
+                let mut search = available;
+                if first && search.last() == Some(&b'\n') {
+                    search = &search[..search.len() - 1];
+                }
+                match search.iter().rposition(|&b| b == b'\n') {
+                    Some(i) => (available.len() - i - 1, true),
+                    None => (available.len(), false),
+                }
+            };
+            first = false;
+            let end = self.buf_pos;
+            segments.push(self.buf[end - n..end].to_vec());
+            self.buf_pos -= n;
+            self.absolute_pos -= n as u64;
+            if found {
+                break;
+            }
+        }
+
+        let line: Vec<u8> = segments.into_iter().rev().flat_map(|s| s.into_iter()).collect();
+        let n = line.len();
+        match String::from_utf8(line) {
+            Ok(line) => {
+                buf.push_str(&line);
+                Ok(n)
+            }
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                         "stream did not contain valid UTF-8")),
+        }
+    }
+
+    /// Returns an iterator over the lines of this reader, starting with the last one.
+    ///
+    /// The iterator seeks to the end of the stream before yielding the first line.
+    /// Lines are returned without their terminator (`\n` or `\r\n`), like `BufRead::lines`.
+    /// A trailing newline at the end of the stream doesn't start an empty line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("one\r\ntwo\nthree"));
+    /// let lines: Vec<String> = reader.rev_lines().map(|l| l.unwrap()).collect();
+    /// assert_eq!(lines, ["three", "two", "one"]);
+    /// ```
+    pub fn rev_lines(&mut self) -> RevLines<'_, R> {
+        RevLines { reader: self, started: false }
+    }
+}
+
+/// An iterator over the lines of a `BufReader`, from the end towards the start.
+///
+/// This struct is created by `BufReader::rev_lines`.
+pub struct RevLines<'a, R: 'a> {
+    reader: &'a mut BufReader<R>,
+    started: bool,
+}
+
+impl<'a, R: Read + Seek> Iterator for RevLines<'a, R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        if !self.started {
+            self.started = true;
+            if let Err(e) = self.reader.seek(SeekFrom::End(0)) {
+                return Some(Err(e));
+            }
+        }
+        let mut line = String::new();
+        match self.reader.read_line_backward(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn rev_lines(data: &str, cap: usize) -> Vec<String> {
+        let mut reader = BufReader::with_capacity(cap, Cursor::new(data.as_bytes().to_vec()));
+        reader.rev_lines().map(|l| l.unwrap()).collect()
+    }
+
+    #[test]
+    fn trailing_newline() {
+        assert_eq!(rev_lines("a\nbc\n", 16), ["bc", "a"]);
+        assert_eq!(rev_lines("a\nbc", 16), ["bc", "a"]);
+        assert_eq!(rev_lines("a\n\n", 16), ["", "a"]);
+        assert_eq!(rev_lines("\n", 16), [""]);
+        assert_eq!(rev_lines("", 16), Vec::<String>::new());
+    }
+
+    #[test]
+    fn crlf() {
+        assert_eq!(rev_lines("a\r\nbc\r\n", 16), ["bc", "a"]);
+        assert_eq!(rev_lines("a\r\n\r\nb", 16), ["b", "", "a"]);
+    }
+
+    #[test]
+    fn small_buffer() {
+        let data = "first line\nsecond line\n\nlast line without newline";
+        assert_eq!(rev_lines(data, 3),
+                   ["last line without newline", "", "second line", "first line"]);
+        assert_eq!(rev_lines(data, 1),
+                   ["last line without newline", "", "second line", "first line"]);
+    }
+
+    #[test]
+    fn read_line_backward() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new(b"ab\ncd\n".to_vec()));
+        reader.seek(SeekFrom::Start(5)).unwrap();
+
+        let mut line = String::new();
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 2);
+        assert_eq!(line, "cd");
+        assert_eq!(reader.position(), 3);
+
+        line.clear();
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 3);
+        assert_eq!(line, "ab\n");
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 0);
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn behind_end() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new(b"ab\ncd\n".to_vec()));
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        let mut line = String::new();
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 3);
+        assert_eq!(line, "cd\n");
+        assert_eq!(reader.position(), 3);
+
+        let mut reader = BufReader::with_block_cache(4, 2, Cursor::new(b"ab\ncd".to_vec()));
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        line.clear();
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 2);
+        assert_eq!(line, "cd");
+    }
+
+    #[test]
+    fn behind_end_keeps_len_hint() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new(b"ab\ncd\nef".to_vec()));
+        reader.set_len_hint(6);
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        let mut line = String::new();
+        // The hint lies before the position, so the length is queried again
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 2);
+        assert_eq!(line, "ef");
+
+        let mut reader = BufReader::with_capacity(4, Cursor::new(b"ab\ncd\n".to_vec()));
+        reader.set_len_hint(10);
+        reader.seek(SeekFrom::Start(8)).unwrap();
+        line.clear();
+        // A hint covering the position is trusted and kept
+        assert_eq!(reader.read_line_backward(&mut line).unwrap(), 0);
+        assert_eq!(reader.len().unwrap(), 10);
+    }
+
+    #[test]
+    fn block_cache() {
+        let data = b"one\ntwo\nthree\nfour\n".to_vec();
+        let mut reader = BufReader::with_block_cache(4, 2, Cursor::new(data));
+        let lines: Vec<String> = reader.rev_lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, ["four", "three", "two", "one"]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut reader = BufReader::new(Cursor::new(vec![b'a', b'\n', 0xff, b'\n']));
+        let mut lines = reader.rev_lines();
+        assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lines.next().unwrap().unwrap(), "a");
+        assert!(lines.next().is_none());
+    }
+}
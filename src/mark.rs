@@ -0,0 +1,275 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Mark/reset checkpoints for speculative parsing.
+//!
+//! Marks form a stack, resetting to a mark drops all marks created after it.
+//! With `BufReader::pin_marks` the bytes from the oldest mark onwards are kept
+//! in the buffer, so resetting doesn't need to seek the inner reader.
+//! `BufReader::try_parse` wraps a mark in a closure scope.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use BufReader;
+
+/// A checkpoint created by `BufReader::mark`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mark {
+    pos: u64,      // absolute position of the mark
+    depth: usize,  // index in the mark stack
+}
+
+impl Mark {
+    /// Returns the absolute position of this mark.
+    pub fn position(&self) -> u64 { self.pos }
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Marks the current position and returns a token to `reset` to it.
+    ///
+    /// Marks can be nested. A mark stays active until it is passed to `release`
+    /// or an older mark is reset or released.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new(b"1234abc".to_vec()));
+    /// let mark = reader.mark();
+    ///
+    /// let mut buf = [0; 4];
+    /// reader.read_exact(&mut buf).unwrap();
+    /// if buf.iter().all(|b| b.is_ascii_alphabetic()) {
+    ///     reader.release(mark);
+    /// } else {
+    ///     reader.reset(mark).unwrap();
+    /// }
+    /// assert_eq!(reader.position(), 0);
+    /// ```
+    pub fn mark(&mut self) -> Mark {
+        let mark = Mark { pos: self.absolute_pos, depth: self.marks.len() };
+        self.marks.push(mark.pos);
+        mark
+    }
+
+    /// Returns to the position of `mark` and drops all marks created after it.
+    ///
+    /// `mark` itself stays active, so it can be reset to repeatedly.
+    /// Resetting to a mark which has been dropped already only seeks to its position.
+    /// If the marked bytes are still buffered the inner reader is not accessed,
+    /// which is guaranteed for pinned marks within their read limit.
+    pub fn reset(&mut self, mark: Mark) -> io::Result<u64> {
+        if self.marks.get(mark.depth) == Some(&mark.pos) {
+            self.marks.truncate(mark.depth + 1);
+        }
+        self.seek(SeekFrom::Start(mark.pos))
+    }
+
+    /// Drops `mark` and all marks created after it, unpinning their bytes.
+    pub fn release(&mut self, mark: Mark) {
+        if self.marks.get(mark.depth) == Some(&mark.pos) {
+            self.marks.truncate(mark.depth);
+        }
+    }
+
+    /// Runs `parse` and restores the current position if it fails.
+    ///
+    /// On `Ok` the new position is kept, on `Err` the reader is reset to the
+    /// position before the call. Calls can be nested, the restore works like
+    /// `reset` with a mark and is served from the buffer if possible.
+    /// Combine with `pin_marks` to keep the inner reader untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `parse`, or the error of the restoring seek if that fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{self, Cursor};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new([0x01, 0x02, 0xff, 0xff]));
+    ///
+    /// let magic = reader.try_parse(|r| {
+    ///     match r.read_u16_be()? {
+    ///         0x0102 => Ok(r.read_u16_be()?),
+    ///         _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad magic")),
+    ///     }
+    /// });
+    /// assert_eq!(magic.unwrap(), 0xffff);
+    ///
+    /// let err = reader.try_parse(|r| r.read_u32_le());
+    /// assert!(err.is_err());
+    /// assert_eq!(reader.position(), 4);
+    /// ```
+    pub fn try_parse<T, E, F>(&mut self, parse: F) -> Result<T, E>
+        where F: FnOnce(&mut BufReader<R>) -> Result<T, E>,
+              E: From<io::Error>
+    {
+        let mark = self.mark();
+        match parse(self) {
+            Ok(value) => {
+                self.release(mark);
+                Ok(value)
+            }
+            Err(e) => {
+                let reset = self.reset(mark);
+                self.release(mark);
+                reset?;
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tests::{counting_cursor as cursor, CountingReader};
+
+    fn read_n(reader: &mut BufReader<CountingReader<Cursor<Vec<u8>>>>, n: usize) -> Vec<u8> {
+        let mut buf = vec![0; n];
+        reader.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn nested() {
+        let mut reader = BufReader::with_capacity(4, cursor(64));
+        read_n(&mut reader, 2);
+        let outer = reader.mark();
+        read_n(&mut reader, 5);
+        let inner = reader.mark();
+        assert_eq!(inner.position(), 7);
+        read_n(&mut reader, 3);
+
+        assert_eq!(reader.reset(inner).unwrap(), 7);
+        assert_eq!(read_n(&mut reader, 2), [7, 8]);
+        assert_eq!(reader.reset(outer).unwrap(), 2);
+        assert_eq!(read_n(&mut reader, 2), [2, 3]);
+
+        // `inner` was dropped by resetting `outer`
+        assert_eq!(reader.marks, [2]);
+        reader.reset(inner).unwrap();
+        assert_eq!(reader.marks, [2]);
+        reader.release(outer);
+        assert!(reader.marks.is_empty());
+    }
+
+    #[test]
+    fn pinned_reset_stays_buffered() {
+        let mut reader = BufReader::with_capacity(4, cursor(64)).pin_marks(16);
+        read_n(&mut reader, 3);
+        let mark = reader.mark();
+        assert_eq!(read_n(&mut reader, 12), (3..15).collect::<Vec<u8>>());
+
+        let seeks = reader.inner.seeks;
+        let reads = reader.inner.reads;
+        reader.reset(mark).unwrap();
+        assert_eq!(read_n(&mut reader, 12), (3..15).collect::<Vec<u8>>());
+        reader.reset(mark).unwrap();
+        assert_eq!((reader.inner.seeks, reader.inner.reads), (seeks, reads));
+    }
+
+    #[test]
+    fn read_limit_exceeded() {
+        let mut reader = BufReader::with_capacity(4, cursor(64)).pin_marks(6);
+        let mark = reader.mark();
+        read_n(&mut reader, 12);
+
+        // Bytes were unpinned, reset falls back to a seek
+        let seeks = reader.inner.seeks;
+        reader.reset(mark).unwrap();
+        assert_eq!(read_n(&mut reader, 2), [0, 1]);
+        assert_eq!(reader.inner.seeks, seeks + 1);
+    }
+
+    #[test]
+    fn release_unpins() {
+        let mut reader = BufReader::with_capacity(4, cursor(64)).pin_marks(64);
+        let mark = reader.mark();
+        read_n(&mut reader, 8);
+        reader.release(mark);
+        read_n(&mut reader, 8);
+        assert_eq!(reader.buffer().len(), 0);
+        assert!(reader.capacity() <= 8);
+    }
+
+    #[test]
+    fn pinned_with_adaptive_growth() {
+        let mut reader = BufReader::with_capacity(4, cursor(64)).adaptive(4, 64).pin_marks(1000);
+        read_n(&mut reader, 2);
+        let mark = reader.mark();
+        read_n(&mut reader, 40);
+        assert!(reader.fill_size() > 4);
+
+        let seeks = reader.inner.seeks;
+        reader.reset(mark).unwrap();
+        assert_eq!(read_n(&mut reader, 40), (2..42).collect::<Vec<u8>>());
+        assert_eq!(reader.inner.seeks, seeks);
+    }
+
+    #[test]
+    fn peek_keeps_pinned_bytes() {
+        let mut reader = BufReader::with_capacity(16, cursor(64)).pin_marks(40);
+        let mark = reader.mark();
+        read_n(&mut reader, 13);
+        assert_eq!(reader.peek(7).unwrap(), [13, 14, 15, 16, 17, 18, 19]);
+
+        let reads = reader.inner.reads;
+        reader.reset(mark).unwrap();
+        assert_eq!(read_n(&mut reader, 20), (0..20).collect::<Vec<u8>>());
+        assert_eq!(reader.inner.reads, reads);
+    }
+
+    #[test]
+    fn try_parse_nested() {
+        let mut reader = BufReader::with_capacity(4, cursor(64)).pin_marks(32);
+        let seeks = reader.inner.seeks;
+
+        let result: io::Result<u8> = reader.try_parse(|r| {
+            r.read_u32_le()?;
+            // Inner failure rewinds to 4 only
+            let inner: io::Result<()> = r.try_parse(|r| {
+                r.read_u16_le()?;
+                Err(io::Error::other("inner"))
+            });
+            assert!(inner.is_err());
+            assert_eq!(r.position(), 4);
+            // Inner success commits
+            r.try_parse(|r| r.read_u8())?;
+            assert_eq!(r.position(), 5);
+            Err(io::Error::other("outer"))
+        });
+        assert!(result.is_err());
+        assert_eq!(reader.position(), 0);
+        assert!(reader.marks.is_empty());
+        assert_eq!(reader.inner.seeks, seeks);
+
+        assert_eq!(reader.try_parse(|r| r.read_u8()).unwrap(), 0);
+        assert_eq!(reader.position(), 1);
+    }
+
+    #[test]
+    fn try_parse_failed_restore() {
+        // Without history the start is gone once the buffer was refilled
+        let data: Vec<u8> = (0..16).collect();
+        let mut reader = BufReader::with_history(4, 0, &data[..]);
+
+        let result: io::Result<()> = reader.try_parse(|r| {
+            r.read_u64_le()?;
+            Err(io::Error::other("parse"))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert!(reader.marks.is_empty());
+    }
+}
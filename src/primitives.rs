@@ -0,0 +1,115 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Endian-aware readers for primitive types.
+//!
+//! Values are decoded straight from the internal buffer if it holds enough bytes,
+//! otherwise they are assembled with `read_exact` across window boundaries.
+
+use std::io::{self, Read, Seek};
+
+use BufReader;
+
+macro_rules! read_primitive {
+    ($name:ident, $ty:ty, $from:ident, $doc:expr) => {
+        #[doc = $doc]
+        pub fn $name(&mut self) -> io::Result<$ty> {
+            let mut bytes = [0; ::std::mem::size_of::<$ty>()];
+            self.read_array(&mut bytes)?;
+            Ok(<$ty>::$from(bytes))
+        }
+    };
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Fills `out` from the buffer, falling back to `read_exact` if it holds too few bytes
+    fn read_array(&mut self, out: &mut [u8]) -> io::Result<()> {
+        if self.available() >= out.len() {
+            out.copy_from_slice(&self.buf[self.buf_pos..self.buf_pos + out.len()]);
+            self.buf_pos += out.len();
+            self.absolute_pos += out.len() as u64;
+            Ok(())
+        } else {
+            self.read_exact(out)
+        }
+    }
+
+    read_primitive!(read_u8, u8, from_le_bytes, "Reads an `u8`.");
+    read_primitive!(read_i8, i8, from_le_bytes, "Reads an `i8`.");
+
+    read_primitive!(read_u16_le, u16, from_le_bytes, "Reads a little-endian `u16`.");
+    read_primitive!(read_u16_be, u16, from_be_bytes, "Reads a big-endian `u16`.");
+    read_primitive!(read_i16_le, i16, from_le_bytes, "Reads a little-endian `i16`.");
+    read_primitive!(read_i16_be, i16, from_be_bytes, "Reads a big-endian `i16`.");
+
+    read_primitive!(read_u32_le, u32, from_le_bytes, "Reads a little-endian `u32`.");
+    read_primitive!(read_u32_be, u32, from_be_bytes, "Reads a big-endian `u32`.");
+    read_primitive!(read_i32_le, i32, from_le_bytes, "Reads a little-endian `i32`.");
+    read_primitive!(read_i32_be, i32, from_be_bytes, "Reads a big-endian `i32`.");
+
+    read_primitive!(read_u64_le, u64, from_le_bytes, "Reads a little-endian `u64`.");
+    read_primitive!(read_u64_be, u64, from_be_bytes, "Reads a big-endian `u64`.");
+    read_primitive!(read_i64_le, i64, from_le_bytes, "Reads a little-endian `i64`.");
+    read_primitive!(read_i64_be, i64, from_be_bytes, "Reads a big-endian `i64`.");
+
+    read_primitive!(read_f32_le, f32, from_le_bytes, "Reads a little-endian `f32`.");
+    read_primitive!(read_f32_be, f32, from_be_bytes, "Reads a big-endian `f32`.");
+    read_primitive!(read_f64_le, f64, from_le_bytes, "Reads a little-endian `f64`.");
+    read_primitive!(read_f64_be, f64, from_be_bytes, "Reads a big-endian `f64`.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    #[test]
+    fn byte_order() {
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        let mut reader = BufReader::new(Cursor::new(data));
+
+        assert_eq!(reader.read_u16_le().unwrap(), 0x0201);
+        assert_eq!(reader.read_u16_be().unwrap(), 0x0304);
+        assert_eq!(reader.read_u32_le().unwrap(), 0x08070605);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(reader.read_u64_be().unwrap(), 0x0102030405060708);
+        assert_eq!(reader.read_u64_le().unwrap(), 0x100f0e0d0c0b0a09);
+        assert!(reader.read_u8().is_err());
+        assert_eq!(reader.position(), 16);
+    }
+
+    #[test]
+    fn signed_and_float() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(-2i16).to_be_bytes());
+        data.extend_from_slice(&(-3i32).to_le_bytes());
+        data.extend_from_slice(&(-4i64).to_be_bytes());
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        data.extend_from_slice(&(-0.25f64).to_be_bytes());
+        data.push(0xff);
+        let mut reader = BufReader::new(Cursor::new(data));
+
+        assert_eq!(reader.read_i16_be().unwrap(), -2);
+        assert_eq!(reader.read_i32_le().unwrap(), -3);
+        assert_eq!(reader.read_i64_be().unwrap(), -4);
+        assert_eq!(reader.read_f32_le().unwrap(), 1.5);
+        assert_eq!(reader.read_f64_be().unwrap(), -0.25);
+        assert_eq!(reader.read_i8().unwrap(), -1);
+    }
+
+    #[test]
+    fn across_refill() {
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let mut reader = BufReader::with_capacity(3, Cursor::new(data));
+
+        assert_eq!(reader.read_u8().unwrap(), 1);
+        assert_eq!(reader.read_u32_be().unwrap(), 0x02030405);
+        assert_eq!(reader.read_u32_le().unwrap(), 0x09080706);
+        assert_eq!(reader.read_u16_le().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
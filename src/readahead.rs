@@ -0,0 +1,351 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Sequential read-ahead on a background thread.
+//!
+//! A worker thread owns the underlying reader and reads the next chunk while the
+//! caller consumes the current one. Every seek starts a new epoch, chunks which
+//! were read for an older epoch are discarded by the receiving side.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+enum Command {
+    Seek(u64, SeekFrom),   // start a new epoch at the given position
+    Recycle(Vec<u8>),      // hand a consumed chunk back for reuse
+}
+
+enum Reply {
+    Seeked(u64, io::Result<u64>),
+    Data(u64, io::Result<Vec<u8>>),
+}
+
+/// A reader which prefetches the next chunk of the underlying reader on a worker thread.
+///
+/// `ReadAhead` is meant to be wrapped by a `BufReader`, see `BufReader::with_read_ahead`.
+/// Seeking redirects the worker, chunks which are already in flight are discarded.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Read};
+/// use seek_bufread::ReadAhead;
+///
+/// let mut reader = ReadAhead::new(4, Cursor::new(vec![0, 1, 2, 3, 4, 5]));
+/// let mut buf = Vec::new();
+/// reader.read_to_end(&mut buf).unwrap();
+/// assert_eq!(buf, [0, 1, 2, 3, 4, 5]);
+/// ```
+pub struct ReadAhead<R> {
+    commands: Sender<Command>,  // requests to the worker
+    replies: Receiver<Reply>,   // chunks and seek results from the worker
+    worker: JoinHandle<R>,      // returns the inner reader on shutdown
+    chunk: Vec<u8>,             // current chunk
+    chunk_pos: usize,           // position within chunk
+    pos: u64,                   // absolute position
+    epoch: u64,                 // incremented by every seek of the worker
+    active: bool,               // worker is reading chunks for the current epoch
+}
+
+impl<R: Read + Seek + Send + 'static> ReadAhead<R> {
+    /// Creates a new `ReadAhead` which reads chunks of `chunk_size` bytes.
+    ///
+    /// The worker starts reading at the current position of `inner`,
+    /// which is assumed to be the start of the stream.
+    pub fn new(chunk_size: usize, inner: R) -> ReadAhead<R> {
+        let (commands, command_rx) = mpsc::channel();
+        // One chunk in the queue plus the one being read by the worker
+        let (reply_tx, replies) = mpsc::sync_channel(1);
+        let worker = thread::spawn(move || work(inner, chunk_size, command_rx, reply_tx));
+        ReadAhead {
+            commands,
+            replies,
+            worker,
+            chunk: Vec::new(),
+            chunk_pos: 0,
+            pos: 0,
+            epoch: 0,
+            active: true,
+        }
+    }
+
+    /// Stops the worker thread and returns the inner reader, positioned at the current position.
+    pub fn into_inner(self) -> io::Result<R> {
+        let pos = self.pos;
+        // Disconnecting both channels makes the worker return
+        drop(self.commands);
+        drop(self.replies);
+        let mut inner = match self.worker.join() {
+            Ok(inner) => inner,
+            Err(_) => return Err(io::Error::other("read-ahead worker panicked")),
+        };
+        inner.seek(SeekFrom::Start(pos))?;
+        Ok(inner)
+    }
+
+    /// Redirects the worker and returns the resulting epoch
+    fn redirect(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.epoch += 1;
+        self.active = true;
+        let chunk = mem::take(&mut self.chunk);
+        self.chunk_pos = 0;
+        if chunk.capacity() > 0 {
+            // A dead worker is reported by the next receive
+            let _ = self.commands.send(Command::Recycle(chunk));
+        }
+        self.commands.send(Command::Seek(self.epoch, pos)).map_err(|_| disconnected())?;
+        Ok(self.epoch)
+    }
+
+    /// Receives the next reply of the current epoch
+    fn receive(&mut self) -> io::Result<Reply> {
+        loop {
+            let reply = self.replies.recv().map_err(|_| disconnected())?;
+            let epoch = match reply {
+                Reply::Seeked(epoch, _) | Reply::Data(epoch, _) => epoch,
+            };
+            if epoch == self.epoch {
+                return Ok(reply);
+            }
+            if let Reply::Data(_, Ok(chunk)) = reply {
+                let _ = self.commands.send(Command::Recycle(chunk));
+            }
+        }
+    }
+
+    /// Replaces the current chunk with the next one, returns `false` at EOF
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if !self.active {
+            // Restart after EOF or an error
+            let pos = self.pos;
+            self.redirect(SeekFrom::Start(pos))?;
+        }
+        loop {
+            match self.receive()? {
+                Reply::Seeked(_, Ok(_)) => {}
+                Reply::Seeked(_, Err(e)) | Reply::Data(_, Err(e)) => {
+                    self.active = false;
+                    return Err(e);
+                }
+                Reply::Data(_, Ok(chunk)) => {
+                    if chunk.is_empty() {
+                        self.active = false;
+                        return Ok(false);
+                    }
+                    let old = mem::replace(&mut self.chunk, chunk);
+                    self.chunk_pos = 0;
+                    if old.capacity() > 0 {
+                        let _ = self.commands.send(Command::Recycle(old));
+                    }
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Read for ReadAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.chunk_pos == self.chunk.len() && !self.next_chunk()? {
+            return Ok(0);
+        }
+        let n = (&self.chunk[self.chunk_pos..]).read(buf)?;
+        self.chunk_pos += n;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Seek for ReadAhead<R> {
+    /// Seeks to an offset, redirecting the worker thread if required.
+    ///
+    /// `SeekFrom::End(_)` waits for the worker to report the resulting position.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) => match offset(self.pos, n) {
+                Some(n) => n,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "invalid seek to a negative or overflowing position")),
+            },
+            SeekFrom::End(_) => {
+                self.redirect(pos)?;
+                return match self.receive()? {
+                    Reply::Seeked(_, Ok(n)) => {
+                        self.pos = n;
+                        Ok(n)
+                    }
+                    Reply::Seeked(_, Err(e)) => {
+                        self.active = false;
+                        Err(e)
+                    }
+                    Reply::Data(..) => unreachable!("worker replies to a seek first"),
+                };
+            }
+        };
+
+        // Stay in the current chunk if possible
+        let chunk_start = self.pos - self.chunk_pos as u64;
+        if target >= chunk_start && target <= chunk_start + self.chunk.len() as u64 {
+            self.chunk_pos = (target - chunk_start) as usize;
+            self.pos = target;
+            return Ok(target);
+        }
+        self.redirect(SeekFrom::Start(target))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+/// Adds the signed offset `n` to `pos`
+fn offset(pos: u64, n: i64) -> Option<u64> {
+    if n >= 0 {
+        pos.checked_add(n as u64)
+    } else {
+        pos.checked_sub(n.unsigned_abs())
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "read-ahead worker is gone")
+}
+
+/// Worker loop, returns the inner reader once the `ReadAhead` is gone
+fn work<R: Read + Seek>(mut inner: R,
+                        chunk_size: usize,
+                        commands: Receiver<Command>,
+                        replies: SyncSender<Reply>) -> R {
+    let mut epoch = 0;
+    let mut active = true;
+    let mut spare: Vec<Vec<u8>> = Vec::new();
+    loop {
+        let command = if active {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return inner,
+            }
+        } else {
+            // Nothing to prefetch, wait for the next seek
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return inner,
+            }
+        };
+
+        match command {
+            Some(Command::Seek(new_epoch, pos)) => {
+                epoch = new_epoch;
+                let result = inner.seek(pos);
+                active = result.is_ok();
+                if replies.send(Reply::Seeked(epoch, result)).is_err() {
+                    return inner;
+                }
+                continue;
+            }
+            Some(Command::Recycle(chunk)) => {
+                spare.push(chunk);
+                continue;
+            }
+            None => {}
+        }
+
+        let mut chunk = spare.pop().unwrap_or_default();
+        chunk.resize(chunk_size, 0);
+        let result = loop {
+            match inner.read(&mut chunk) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => break result,
+            }
+        };
+        let reply = match result {
+            Ok(n) => {
+                chunk.truncate(n);
+                active = n > 0;
+                Reply::Data(epoch, Ok(chunk))
+            }
+            Err(e) => {
+                active = false;
+                Reply::Data(epoch, Err(e))
+            }
+        };
+        if replies.send(reply).is_err() {
+            return inner;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    fn cursor(len: usize) -> Cursor<Vec<u8>> {
+        Cursor::new((0..len).map(|i| i as u8).collect())
+    }
+
+    #[test]
+    fn read_sequential() {
+        let mut reader = ReadAhead::new(3, cursor(10));
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn seek_redirects_worker() {
+        let mut reader = ReadAhead::new(4, cursor(64));
+
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1]);
+
+        // Inside the current chunk
+        reader.seek(SeekFrom::Current(1)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [3, 4]);
+
+        reader.seek(SeekFrom::Start(40)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [40, 41]);
+
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [20, 21]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 62);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [62, 63]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        // Restarts after EOF
+        reader.seek(SeekFrom::Current(-3)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [61, 62]);
+    }
+
+    #[test]
+    fn into_inner() {
+        let mut reader = ReadAhead::new(4, cursor(32));
+
+        let mut buf = [0; 6];
+        reader.read_exact(&mut buf).unwrap();
+        let mut inner = reader.into_inner().unwrap();
+        let mut buf = [0; 2];
+        inner.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [6, 7]);
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Streaming regex search, enabled by the `regex` feature.
+//!
+//! Consumed bytes are collected in a window which is searched with `Regex::find_at`.
+//! A match is only reported once the window extends `max_match_len` bytes past its
+//! start and a few bytes of look-ahead context past its end, so a refill can't change
+//! it anymore. Bytes before the earliest possible match start are discarded, except
+//! for a few bytes of look-behind context.
+
+use std::io::{self, BufRead, Read, Seek};
+
+use regex::bytes::Regex;
+
+use BufReader;
+
+/// Number of bytes kept around a match for assertions like `\b` or `$`.
+///
+/// This covers the longest UTF-8 encoded char.
+const CONTEXT: usize = 4;
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Returns an iterator over the matches of `regex`, starting at the current position.
+    ///
+    /// Matches are reported with absolute offsets, even if they cross refills.
+    /// Matches longer than `max_match_len` bytes may be reported shortened or
+    /// not at all. The reader is advanced as the iterator reads ahead, seek to
+    /// `Match::start` to read a match together with its context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_match_len` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate regex;
+    /// extern crate seek_bufread;
+    ///
+    /// use std::io::Cursor;
+    /// use regex::bytes::Regex;
+    /// use seek_bufread::BufReader;
+    ///
+    /// # fn main() {
+    /// let log = "ok\nERROR 17\nok\nERROR 23\n";
+    /// let mut reader = BufReader::with_capacity(4, Cursor::new(log));
+    /// let re = Regex::new(r"ERROR \d+").unwrap();
+    ///
+    /// let offsets: Vec<u64> = reader.find_iter(&re, 64).map(|m| m.unwrap().start()).collect();
+    /// assert_eq!(offsets, [3, 15]);
+    /// # }
+    /// ```
+    pub fn find_iter<'r>(&mut self, regex: &'r Regex, max_match_len: usize) -> Matches<'r, '_, R> {
+        assert!(max_match_len > 0, "maximum match length must be non-zero");
+        Matches {
+            base: self.absolute_pos,
+            reader: self,
+            regex,
+            max_len: max_match_len,
+            window: Vec::new(),
+            from: 0,
+            last_end: None,
+            eof: false,
+        }
+    }
+}
+
+/// A match of a regex in a `BufReader`, created by `Matches`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    start: u64,
+    end: u64,
+    bytes: Vec<u8>,
+}
+
+impl Match {
+    /// Returns the absolute offset of the first byte of the match.
+    pub fn start(&self) -> u64 { self.start }
+
+    /// Returns the absolute offset after the last byte of the match.
+    pub fn end(&self) -> u64 { self.end }
+
+    /// Returns the matched bytes.
+    pub fn as_bytes(&self) -> &[u8] { &self.bytes }
+
+    /// Consumes the match and returns the matched bytes.
+    pub fn into_bytes(self) -> Vec<u8> { self.bytes }
+}
+
+/// An iterator over the regex matches in a `BufReader`.
+///
+/// This struct is created by `BufReader::find_iter`.
+pub struct Matches<'r, 'a, R: 'a> {
+    reader: &'a mut BufReader<R>,
+    regex: &'r Regex,
+    max_len: usize,
+    window: Vec<u8>,          // consumed bytes which may still be part of a match
+    base: u64,                // absolute position of window[0]
+    from: usize,              // index in window where the next match may start
+    last_end: Option<usize>,  // end of the previous match, empty matches there are skipped
+    eof: bool,
+}
+
+impl<'r, 'a, R: Read + Seek> Matches<'r, 'a, R> {
+    /// Returns the leftmost match in the window as a pair of indices
+    fn search(&self) -> Option<(usize, usize)> {
+        let mut at = self.from;
+        while at <= self.window.len() {
+            match self.regex.find_at(&self.window, at) {
+                Some(m) if m.start() == m.end() && Some(m.end()) == self.last_end => at = m.end() + 1,
+                found => return found.map(|m| (m.start(), m.end())),
+            }
+        }
+        None
+    }
+
+    /// Discards bytes which can't be part of a match anymore
+    fn trim(&mut self) {
+        let n = self.from.saturating_sub(CONTEXT);
+        if n > 0 {
+            self.window.drain(..n);
+            self.base += n as u64;
+            self.from -= n;
+            self.last_end = self.last_end.and_then(|end| end.checked_sub(n));
+        }
+    }
+
+    /// Appends the next chunk of the reader to the window
+    fn read_more(&mut self) -> io::Result<()> {
+        let n = {
+            let buf = self.reader.fill_buf()?;
+            self.window.extend_from_slice(buf);
+            buf.len()
+        };
+        self.reader.consume(n);
+        self.eof = n == 0;
+        Ok(())
+    }
+}
+
+impl<'r, 'a, R: Read + Seek> Iterator for Matches<'r, 'a, R> {
+    type Item = io::Result<Match>;
+
+    fn next(&mut self) -> Option<io::Result<Match>> {
+        loop {
+            match self.search() {
+                // More data can't change a match which has `max_len` bytes following its start,
+                // nor end assertions with `CONTEXT` bytes following its end
+                Some((start, end)) if self.eof || (start + self.max_len <= self.window.len()
+                                                   && end + CONTEXT <= self.window.len()) => {
+                    self.from = end;
+                    self.last_end = Some(end);
+                    return Some(Ok(Match {
+                        start: self.base + start as u64,
+                        end: self.base + end as u64,
+                        bytes: self.window[start..end].to_vec(),
+                    }));
+                }
+                None if self.eof => return None,
+                None => {
+                    // No match can start this far from the window end
+                    let limit = (self.window.len() + 1).saturating_sub(self.max_len);
+                    self.from = self.from.max(limit);
+                }
+                Some(_) => {}
+            }
+            self.trim();
+            if let Err(e) = self.read_more() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    fn offsets(data: &str, pattern: &str, cap: usize, max_len: usize) -> Vec<(u64, u64)> {
+        let mut reader = BufReader::with_capacity(cap, Cursor::new(data.as_bytes().to_vec()));
+        let re = Regex::new(pattern).unwrap();
+        let matches = reader.find_iter(&re, max_len).map(|m| m.unwrap());
+        matches.map(|m| (m.start(), m.end())).collect()
+    }
+
+    #[test]
+    fn across_refills() {
+        let data = "xx abcdef yy abcdef";
+        for cap in 1..12 {
+            assert_eq!(offsets(data, "abc[a-z]+", cap, 16), [(3, 9), (13, 19)], "capacity {}", cap);
+        }
+    }
+
+    #[test]
+    fn greedy_match_is_complete() {
+        // The match could be extended by every refill
+        assert_eq!(offsets("aaaaaaaaab", "a+", 2, 32), [(0, 9)]);
+        // Limited by the maximum match length
+        assert_eq!(offsets("aaaaaaaaab", "a+", 2, 4)[0].0, 0);
+    }
+
+    #[test]
+    fn assertions_and_empty_matches() {
+        assert_eq!(offsets("one two three", r"\btw?o", 3, 8), [(4, 7)]);
+        assert_eq!(offsets("ab", "x*", 1, 4), [(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn end_assertions_at_refill() {
+        // The window ends right behind "ab" after the first refill
+        assert_eq!(offsets("xx abc", r"ab\b", 5, 2), []);
+        assert_eq!(offsets("xx abc", "ab$", 5, 2), []);
+        assert_eq!(offsets("xx ab", "ab$", 5, 2), [(3, 5)]);
+        assert_eq!(offsets("xx ab cd", r"ab\b", 5, 2), [(3, 5)]);
+    }
+
+    #[test]
+    fn extract_context() {
+        let data = "0123456789 key=value 0123456789";
+        let mut reader = BufReader::with_capacity(4, Cursor::new(data.as_bytes().to_vec()));
+        let re = Regex::new("key=[a-z]+").unwrap();
+        let m = reader.find_iter(&re, 32).next().unwrap().unwrap();
+        assert_eq!(m.as_bytes(), b"key=value");
+
+        reader.seek(SeekFrom::Start(m.start() - 3)).unwrap();
+        let mut context = [0; 6];
+        reader.read_exact(&mut context).unwrap();
+        assert_eq!(&context, b"89 key");
+    }
+}
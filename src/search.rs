@@ -0,0 +1,426 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Byte pattern search across buffer refills.
+//!
+//! Windows are scanned for candidates with a vectorizable byte search, mismatches
+//! are skipped with Boyer-Moore-Horspool shifts. Searching forwards keeps the last
+//! `pattern.len() - 1` bytes of a window buffered and tops them up like `peek` does.
+//! Searching backwards, and forwards in block cache mode, carries them over to the
+//! next window instead. Either way matches which straddle two windows are found as well.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use BufReader;
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Searches forwards for `pattern` and moves to the start of the first match.
+    ///
+    /// Returns the absolute offset of the match, or `None` if the stream ends
+    /// without one. The position is at the end of the stream then.
+    /// An empty pattern matches at the current position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::with_capacity(4, Cursor::new(b"....PK\x03\x04...".to_vec()));
+    /// assert_eq!(reader.find(b"PK\x03\x04").unwrap(), Some(4));
+    /// assert_eq!(reader.position(), 4);
+    /// assert_eq!(reader.find(b"PK\x05\x06").unwrap(), None);
+    /// ```
+    pub fn find(&mut self, pattern: &[u8]) -> io::Result<Option<u64>> {
+        match self.search_forward(pattern)? {
+            Some(offset) => {
+                self.seek(SeekFrom::Start(offset))?;
+                Ok(Some(offset))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Skips all bytes up to and including the next match of `pattern`.
+    ///
+    /// Returns the number of bytes skipped. If there is no match,
+    /// everything up to the end of the stream is skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{BufRead, Cursor};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new(b"header\r\n\r\nbody".to_vec()));
+    /// assert_eq!(reader.skip_until(b"\r\n\r\n").unwrap(), 10);
+    /// assert_eq!(reader.fill_buf().unwrap(), b"body");
+    /// ```
+    pub fn skip_until(&mut self, pattern: &[u8]) -> io::Result<u64> {
+        let start = self.absolute_pos;
+        if let Some(offset) = self.search_forward(pattern)? {
+            self.seek(SeekFrom::Start(offset + pattern.len() as u64))?;
+        }
+        Ok(self.absolute_pos - start)
+    }
+
+    /// Searches backwards for the last match of `pattern` which ends at or before
+    /// the current position, and moves to its start.
+    ///
+    /// Returns the absolute offset of the match, or `None` if there is none.
+    /// The position is at the start of the stream then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Seek, SeekFrom};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::with_capacity(4, Cursor::new(b"ab--ab--ab".to_vec()));
+    /// reader.seek(SeekFrom::End(-1)).unwrap();
+    /// assert_eq!(reader.rfind(b"ab").unwrap(), Some(4));
+    /// assert_eq!(reader.rfind(b"ab").unwrap(), Some(0));
+    /// assert_eq!(reader.rfind(b"ab").unwrap(), None);
+    /// ```
+    pub fn rfind(&mut self, pattern: &[u8]) -> io::Result<Option<u64>> {
+        if pattern.is_empty() {
+            return Ok(Some(self.absolute_pos));
+        }
+        let m = pattern.len();
+        let shift = backward_shifts(pattern);
+        // Bytes after the position which were already searched
+        let mut carry: Vec<u8> = Vec::with_capacity(2 * m);
+        loop {
+            let pos = self.absolute_pos;
+            let (n, found) = {
+                let window = self.fill_backward()?;
+                if window.is_empty() {
+                    (0, None)
+                } else {
+                    // A match straddling into the carry starts after all others
+                    let tail = &window[window.len() - window.len().min(m - 1)..];
+                    let mut joined = tail.to_vec();
+                    joined.extend_from_slice(&carry);
+                    let found = match rfind_in(&joined, pattern, &shift) {
+                        Some(i) if i < tail.len() => Some(pos - (tail.len() - i) as u64),
+                        _ => rfind_in(window, pattern, &shift)
+                            .map(|i| pos - (window.len() - i) as u64),
+                    };
+                    if found.is_none() {
+                        let mut head = window[..window.len().min(m - 1)].to_vec();
+                        head.extend_from_slice(&carry);
+                        head.truncate(m - 1);
+                        carry = head;
+                    }
+                    (window.len(), found)
+                }
+            };
+            if n == 0 {
+                // Behind the end of the stream, start at its end instead
+                if pos > 0 && self.clamp_to_end()? {
+                    continue;
+                }
+                return Ok(None);
+            }
+            if let Some(offset) = found {
+                self.seek(SeekFrom::Start(offset))?;
+                return Ok(Some(offset));
+            }
+            self.buf_pos -= n;
+            self.absolute_pos -= n as u64;
+        }
+    }
+
+    /// Consumes bytes until a match of `pattern` is found and returns its offset.
+    /// The match is always buffered in full, except in block cache mode.
+    fn search_forward(&mut self, pattern: &[u8]) -> io::Result<Option<u64>> {
+        if pattern.is_empty() {
+            return Ok(Some(self.absolute_pos));
+        }
+        let m = pattern.len();
+        let shift = forward_shifts(pattern);
+        if self.cache.is_some() {
+            // Topping up would detach the page from the cache
+            return self.search_pages(pattern, &shift);
+        }
+        loop {
+            self.fill_for_peek()?;
+            self.fill_contiguous(m)?;
+            let pos = self.absolute_pos;
+            let (n, found) = {
+                let window = self.buffer();
+                match find_in(window, pattern, &shift) {
+                    Some(i) => (0, Some(pos + i as u64)),
+                    // Keep the bytes which may start a match straddling into the next window
+                    None => (window.len() - window.len().min(m - 1), None),
+                }
+            };
+            if found.is_some() {
+                return Ok(found);
+            }
+            if n == 0 {
+                // The stream ends before another `m` bytes
+                let rest = self.available();
+                self.consume(rest);
+                return Ok(None);
+            }
+            self.consume(n);
+        }
+    }
+
+    /// Consumes whole pages until a match of `pattern` is found and returns its offset.
+    /// The match may start before the position, within the carried over bytes
+    /// of the previous page, which is still cached.
+    fn search_pages(&mut self, pattern: &[u8], shift: &[usize; 256]) -> io::Result<Option<u64>> {
+        let m = pattern.len();
+        // Bytes before the position which were already searched
+        let mut carry: Vec<u8> = Vec::with_capacity(2 * m);
+        loop {
+            let pos = self.absolute_pos;
+            let (n, found) = {
+                let window = self.fill_buf()?;
+                if window.is_empty() {
+                    return Ok(None);
+                }
+                // A match straddling from the carry starts before all others
+                let head = &window[..window.len().min(m - 1)];
+                let mut joined = carry.clone();
+                joined.extend_from_slice(head);
+                let found = match find_in(&joined, pattern, shift) {
+                    Some(i) if i < carry.len() => Some(pos - (carry.len() - i) as u64),
+                    _ => find_in(window, pattern, shift).map(|i| pos + i as u64),
+                };
+                if found.is_none() {
+                    carry.extend_from_slice(&window[window.len() - window.len().min(m - 1)..]);
+                    let excess = carry.len().saturating_sub(m - 1);
+                    carry.drain(..excess);
+                }
+                (window.len(), found)
+            };
+            if found.is_some() {
+                return Ok(found);
+            }
+            self.consume(n);
+        }
+    }
+}
+
+/// Returns the Horspool shift for each byte, scanning left to right
+fn forward_shifts(pattern: &[u8]) -> [usize; 256] {
+    let m = pattern.len();
+    let mut shift = [m; 256];
+    for (i, &b) in pattern[..m - 1].iter().enumerate() {
+        shift[b as usize] = m - 1 - i;
+    }
+    shift
+}
+
+/// Returns the Horspool shift for each byte, scanning right to left
+fn backward_shifts(pattern: &[u8]) -> [usize; 256] {
+    let m = pattern.len();
+    let mut shift = [m; 256];
+    for (i, &b) in pattern.iter().enumerate().skip(1).rev() {
+        shift[b as usize] = i;
+    }
+    shift
+}
+
+/// Number of bytes compared at once when scanning for a single byte.
+const CHUNK: usize = 32;
+
+/// Returns the index of the first occurrence of `byte`.
+/// Chunks are tested without an early exit first, which the compiler vectorizes.
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let mut chunks = haystack.chunks_exact(CHUNK);
+    let mut offset = 0;
+    for chunk in &mut chunks {
+        if chunk.iter().fold(false, |found, &b| found | (b == byte)) {
+            return chunk.iter().position(|&b| b == byte).map(|i| offset + i);
+        }
+        offset += CHUNK;
+    }
+    chunks.remainder().iter().position(|&b| b == byte).map(|i| offset + i)
+}
+
+/// Returns the index of the last occurrence of `byte`.
+fn memrchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let mut chunks = haystack.rchunks_exact(CHUNK);
+    let mut end = haystack.len();
+    for chunk in &mut chunks {
+        end -= CHUNK;
+        if chunk.iter().fold(false, |found, &b| found | (b == byte)) {
+            return chunk.iter().rposition(|&b| b == byte).map(|i| end + i);
+        }
+    }
+    chunks.remainder().iter().rposition(|&b| b == byte)
+}
+
+/// Returns the index of the first match of `pattern` in `haystack`.
+///
+/// Candidates are located by scanning for the last byte of `pattern`,
+/// a mismatch skips ahead by its Horspool shift.
+fn find_in(haystack: &[u8], pattern: &[u8], shift: &[usize; 256]) -> Option<usize> {
+    let m = pattern.len();
+    let last = pattern[m - 1];
+    let mut i = 0;
+    while i + m <= haystack.len() {
+        i += memchr(last, &haystack[i + m - 1..])?;
+        if haystack[i..i + m - 1] == pattern[..m - 1] {
+            return Some(i);
+        }
+        i += shift[last as usize];
+    }
+    None
+}
+
+/// Returns the index of the last match of `pattern` in `haystack`.
+///
+/// Candidates are located by scanning for the first byte of `pattern`.
+fn rfind_in(haystack: &[u8], pattern: &[u8], shift: &[usize; 256]) -> Option<usize> {
+    let m = pattern.len();
+    let first = pattern[0];
+    let mut end = (haystack.len() + 1).checked_sub(m)?;
+    while end > 0 {
+        let i = memrchr(first, &haystack[..end])?;
+        if haystack[i + 1..i + m] == pattern[1..] {
+            return Some(i);
+        }
+        end = (i + 1).checked_sub(shift[first as usize])?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tests::CountingReader;
+    use CacheStats;
+
+    fn reader(data: &[u8], cap: usize) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(cap, Cursor::new(data.to_vec()))
+    }
+
+    #[test]
+    fn horspool() {
+        let pattern = b"abcab";
+        let haystack = b"xxabcabcabyyabcab";
+        assert_eq!(find_in(haystack, pattern, &forward_shifts(pattern)), Some(2));
+        assert_eq!(rfind_in(haystack, pattern, &backward_shifts(pattern)), Some(12));
+        assert_eq!(find_in(b"abca", pattern, &forward_shifts(pattern)), None);
+        assert_eq!(rfind_in(b"bcab", pattern, &backward_shifts(pattern)), None);
+
+        let long: Vec<u8> = (0..200).map(|i| if i == 100 || i == 170 { b'a' } else { b'b' }).collect();
+        assert_eq!(find_in(&long, b"ab", &forward_shifts(b"ab")), Some(100));
+        assert_eq!(rfind_in(&long, b"ab", &backward_shifts(b"ab")), Some(170));
+        assert_eq!(rfind_in(&long, b"ba", &backward_shifts(b"ba")), Some(169));
+        assert_eq!(find_in(&long, b"x", &forward_shifts(b"x")), None);
+    }
+
+    #[test]
+    fn find_straddling() {
+        let data = b"0123456789MAGIC0123MAGIC";
+        // Every split of the first match across windows
+        for cap in 1..16 {
+            let mut r = reader(data, cap);
+            assert_eq!(r.find(b"MAGIC").unwrap(), Some(10), "capacity {}", cap);
+            let mut magic = [0; 5];
+            r.read_exact(&mut magic).unwrap();
+            assert_eq!(&magic, b"MAGIC");
+            assert_eq!(r.find(b"MAGIC").unwrap(), Some(19));
+            r.seek(SeekFrom::Current(1)).unwrap();
+            assert_eq!(r.find(b"MAGIC").unwrap(), None);
+            assert_eq!(r.position(), data.len() as u64);
+        }
+    }
+
+    #[test]
+    fn find_straddling_stays_buffered() {
+        let inner = CountingReader::new(Cursor::new(b"012MAGIC--".to_vec()));
+        let mut r = BufReader::with_capacity(4, inner);
+        assert_eq!(r.find(b"MAGIC").unwrap(), Some(3));
+        let mut magic = [0; 5];
+        r.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"MAGIC");
+        assert_eq!(r.inner.seeks, 0);
+
+        // The match is never behind the window, so no history is required
+        let mut r = BufReader::with_history(4, 0, &b"012MAGIC--"[..]);
+        assert_eq!(r.find(b"MAGIC").unwrap(), Some(3));
+        r.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"MAGIC");
+        let mut r = BufReader::with_history(4, 0, &b"012MAGIC--"[..]);
+        assert_eq!(r.skip_until(b"MAGIC").unwrap(), 8);
+        assert_eq!(r.fill_buf().unwrap(), b"--");
+    }
+
+    #[test]
+    fn skip_until() {
+        let mut r = reader(b"a--b--c", 3);
+        assert_eq!(r.skip_until(b"--").unwrap(), 3);
+        assert_eq!(r.skip_until(b"--").unwrap(), 3);
+        assert_eq!(r.skip_until(b"--").unwrap(), 1);
+        assert_eq!(r.skip_until(b"--").unwrap(), 0);
+        assert_eq!(r.skip_until(b"").unwrap(), 0);
+    }
+
+    #[test]
+    fn rfind_straddling() {
+        let data = b"MAGIC0123MAGIC456789";
+        for cap in 1..16 {
+            let mut r = reader(data, cap);
+            r.seek(SeekFrom::End(0)).unwrap();
+            assert_eq!(r.rfind(b"MAGIC").unwrap(), Some(9), "capacity {}", cap);
+            // A match ending exactly at the position is found
+            r.seek(SeekFrom::Start(13)).unwrap();
+            assert_eq!(r.rfind(b"MAGIC").unwrap(), Some(0));
+            assert_eq!(r.rfind(b"MAGIC").unwrap(), None);
+            assert_eq!(r.position(), 0);
+        }
+    }
+
+    #[test]
+    fn find_block_cache_hits() {
+        let mut data = vec![0; 200];
+        data[94..99].copy_from_slice(b"MAGIC");
+        let inner = CountingReader::new(Cursor::new(data));
+        let mut r = BufReader::with_block_cache(16, 4, inner);
+        // Straddles the pages 80..96 and 96..112
+        assert_eq!(r.find(b"MAGIC").unwrap(), Some(94));
+        let mut magic = [0; 5];
+        r.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"MAGIC");
+        assert_eq!(r.cache_stats(), Some(CacheStats { hits: 2, misses: 7 }));
+        assert_eq!(r.inner.reads, 7);
+    }
+
+    #[test]
+    fn behind_end() {
+        let mut r = reader(b"ab--ab", 4);
+        r.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(r.rfind(b"ab").unwrap(), Some(4));
+        assert_eq!(r.rfind(b"ab").unwrap(), Some(0));
+
+        let mut r = BufReader::with_block_cache(4, 2, Cursor::new(b"ab--ab".to_vec()));
+        r.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(r.rfind(b"ab").unwrap(), Some(4));
+        r.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(r.rfind(b"xy").unwrap(), None);
+        assert_eq!(r.position(), 0);
+    }
+
+    #[test]
+    fn block_cache() {
+        let mut data = vec![0; 100];
+        data[45..48].copy_from_slice(b"xyz");
+        let mut r = BufReader::with_block_cache(8, 4, Cursor::new(data));
+        assert_eq!(r.find(b"xyz").unwrap(), Some(45));
+        r.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(r.rfind(b"xyz").unwrap(), Some(45));
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Bounded views into a `BufReader`.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use BufReader;
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Returns a view of the bytes in `range`, which shares the buffer of this reader.
+    ///
+    /// Position 0 of the view is `range.start`. The position of this reader is
+    /// moved lazily by the view and stays where the view left it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range.start` is greater than `range.end`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Cursor, Read, Seek, SeekFrom};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new((0..16).collect::<Vec<u8>>()));
+    /// let mut slice = reader.slice(4..8);
+    ///
+    /// let mut buf = Vec::new();
+    /// slice.read_to_end(&mut buf).unwrap();
+    /// assert_eq!(buf, [4, 5, 6, 7]);
+    ///
+    /// slice.seek(SeekFrom::End(-1)).unwrap();
+    /// assert_eq!(slice.read(&mut [0; 4]).unwrap(), 1);
+    /// ```
+    pub fn slice(&mut self, range: Range<u64>) -> SubReader<'_, R> {
+        assert!(range.start <= range.end, "slice start must not exceed its end");
+        SubReader { reader: self, start: range.start, end: range.end, pos: 0 }
+    }
+}
+
+/// A bounded view of a `BufReader`, implementing `Read`, `BufRead` and `Seek`.
+///
+/// Reads past the end of the view report EOF, seeks past its end are clamped.
+/// This struct is created by `BufReader::slice`.
+pub struct SubReader<'a, R: 'a> {
+    reader: &'a mut BufReader<R>,
+    start: u64,         // absolute position of the view start
+    end: u64,           // absolute position of the view end
+    pos: u64,           // position relative to start
+}
+
+impl<'a, R: Read + Seek> SubReader<'a, R> {
+    /// Returns the length of the view.
+    pub fn len(&self) -> u64 { self.end - self.start }
+
+    /// Returns `true` if the view is empty.
+    pub fn is_empty(&self) -> bool { self.start == self.end }
+
+    /// Returns the position relative to the view start.
+    pub fn position(&self) -> u64 { self.pos }
+
+    /// Returns the number of bytes left in the view
+    fn remaining(&self) -> u64 { self.len() - self.pos }
+
+    /// Moves the parent reader to the position of the view
+    fn sync(&mut self) -> io::Result<()> {
+        let pos = self.start + self.pos;
+        if self.reader.position() != pos {
+            self.reader.seek(SeekFrom::Start(pos))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read + Seek> Read for SubReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = (buf.len() as u64).min(self.remaining()) as usize;
+        if max == 0 {
+            return Ok(0);
+        }
+        self.sync()?;
+        let n = self.reader.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<'a, R: Read + Seek> BufRead for SubReader<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let remaining = self.remaining();
+        if remaining == 0 {
+            return Ok(&[]);
+        }
+        self.sync()?;
+        let buf = self.reader.fill_buf()?;
+        let n = (buf.len() as u64).min(remaining) as usize;
+        Ok(&buf[..n])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = (amt as u64).min(self.remaining());
+        self.reader.consume(amt as usize);
+        self.pos += amt;
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for SubReader<'a, R> {
+    /// Seeks within the view, `SeekFrom::End` is relative to the view end.
+    ///
+    /// Positions past the end of the view are clamped to its end.
+    /// The parent reader is moved by the next read.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n.min(self.len());
+                return Ok(self.pos);
+            }
+            SeekFrom::Current(n) => (self.pos, n),
+            SeekFrom::End(n) => (self.len(), n),
+        };
+        let target = if offset >= 0 {
+            base.saturating_add(offset as u64)
+        } else {
+            match base.checked_sub(offset.unsigned_abs()) {
+                Some(n) => n,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "invalid seek to a negative position")),
+            }
+        };
+        self.pos = target.min(self.len());
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tests::counting_cursor;
+
+    #[test]
+    fn bounds() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new((0..32).collect::<Vec<u8>>()));
+        reader.seek(SeekFrom::Start(20)).unwrap();
+        let mut slice = reader.slice(10..16);
+        assert_eq!(slice.len(), 6);
+
+        let mut buf = [0; 4];
+        slice.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [10, 11, 12, 13]);
+        assert_eq!(slice.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [14, 15]);
+        assert_eq!(slice.read(&mut buf).unwrap(), 0);
+        assert_eq!(slice.fill_buf().unwrap(), []);
+        assert_eq!(reader.position(), 16);
+    }
+
+    #[test]
+    fn seek() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new((0..32).collect::<Vec<u8>>()));
+        let mut slice = reader.slice(8..24);
+
+        assert_eq!(slice.seek(SeekFrom::End(-2)).unwrap(), 14);
+        assert_eq!(slice.fill_buf().unwrap(), [22, 23]);
+        assert_eq!(slice.seek(SeekFrom::End(5)).unwrap(), 16);
+        assert_eq!(slice.seek(SeekFrom::Start(100)).unwrap(), 16);
+        assert_eq!(slice.seek(SeekFrom::Current(-16)).unwrap(), 0);
+        assert!(slice.seek(SeekFrom::Current(-1)).is_err());
+        assert_eq!(slice.position(), 0);
+        assert_eq!(slice.fill_buf().unwrap(), [8, 9, 10, 11]);
+        slice.consume(3);
+        assert_eq!(slice.position(), 3);
+    }
+
+    #[test]
+    fn shares_buffer() {
+        let mut reader = BufReader::with_capacity(16, counting_cursor(32));
+        reader.fill_buf().unwrap();
+        let reads = reader.inner.reads;
+        {
+            let mut slice = reader.slice(2..6);
+            let mut buf = Vec::new();
+            slice.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, [2, 3, 4, 5]);
+        }
+        assert_eq!(reader.inner.reads, reads);
+        assert_eq!(reader.inner.seeks, 0);
+    }
+}
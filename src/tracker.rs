@@ -0,0 +1,214 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Mapping of absolute offsets to line and column numbers.
+//!
+//! Newlines are counted as bytes pass through `consume` for the first time.
+//! The start of every `CHECKPOINT`-th line is recorded, so earlier offsets are
+//! resolved by rescanning from the closest checkpoint. Bytes which were skipped
+//! by a seek or read without `consume` are scanned when they are first queried.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use search::memchr;
+use BufReader;
+
+/// Number of lines between checkpoints.
+const CHECKPOINT: u64 = 1024;
+
+pub struct LineTracker {
+    scanned: u64,          // end of the counted prefix of the stream
+    newlines: u64,         // newlines before `scanned`
+    line_start: u64,       // start of the line containing `scanned`
+    checkpoints: Vec<u64>, // start of every `CHECKPOINT`-th line
+}
+
+impl LineTracker {
+    pub fn new() -> LineTracker {
+        LineTracker { scanned: 0, newlines: 0, line_start: 0, checkpoints: vec![0] }
+    }
+
+    /// Counts the newlines in `bytes`, which start at the absolute offset `start`.
+    /// Bytes before the counted prefix are skipped, bytes after it are ignored.
+    pub fn count(&mut self, start: u64, bytes: &[u8]) {
+        let end = start + bytes.len() as u64;
+        if start > self.scanned || end <= self.scanned {
+            return;
+        }
+        let skip = (self.scanned - start) as usize;
+        let mut i = skip;
+        while let Some(j) = memchr(b'\n', &bytes[i..]) {
+            i += j + 1;
+            self.newlines += 1;
+            self.line_start = start + i as u64;
+            if self.newlines.is_multiple_of(CHECKPOINT) {
+                self.checkpoints.push(self.line_start);
+            }
+        }
+        self.scanned = end;
+    }
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Returns the line and column of the current position, both counted from 1.
+    ///
+    /// Requires `track_lines`, see `line_col_of`.
+    pub fn line_col(&mut self) -> io::Result<(u64, u64)> {
+        let pos = self.absolute_pos;
+        self.line_col_of(pos)
+    }
+
+    /// Returns the line and column of the absolute `offset`, both counted from 1.
+    ///
+    /// Lines are terminated by `\n`, columns count bytes. Offsets up to the
+    /// furthest consumed position are resolved from the buffer or by rescanning
+    /// at most 1024 lines, offsets beyond it are scanned up to once.
+    /// The position is restored afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidInput` if line tracking is not enabled
+    /// or `offset` lies past the end of the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{BufRead, Cursor};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new("let x = 1;\nlet y = ?;\n")).track_lines();
+    ///
+    /// let mut line = String::new();
+    /// reader.read_line(&mut line).unwrap();
+    /// assert_eq!(reader.line_col().unwrap(), (2, 1));
+    /// assert_eq!(reader.line_col_of(19).unwrap(), (2, 9));
+    /// ```
+    pub fn line_col_of(&mut self, offset: u64) -> io::Result<(u64, u64)> {
+        let scanned = match self.tracker {
+            Some(ref tracker) => tracker.scanned,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "line tracking is not enabled")),
+        };
+        let saved = self.absolute_pos;
+        let result = if offset > scanned {
+            // Consuming the gap counts it
+            self.scan(scanned, offset, |_, _| {})
+        } else {
+            Ok(())
+        };
+        let result = result.and_then(|_| self.resolve(offset));
+        self.seek(SeekFrom::Start(saved))?;
+        result
+    }
+
+    /// Resolves an offset within the counted prefix
+    fn resolve(&mut self, offset: u64) -> io::Result<(u64, u64)> {
+        let (line, start) = {
+            let tracker = self.tracker.as_ref().expect("line tracking is enabled");
+            if offset >= tracker.line_start {
+                return Ok((tracker.newlines + 1, offset - tracker.line_start + 1));
+            }
+            let i = tracker.checkpoints.partition_point(|&start| start <= offset) - 1;
+            (i as u64 * CHECKPOINT, tracker.checkpoints[i])
+        };
+        let mut newlines = 0;
+        let mut line_start = start;
+        self.scan(start, offset, |pos, bytes| {
+            let mut i = 0;
+            while let Some(j) = memchr(b'\n', &bytes[i..]) {
+                i += j + 1;
+                newlines += 1;
+                line_start = pos + i as u64;
+            }
+        })?;
+        Ok((line + newlines + 1, offset - line_start + 1))
+    }
+
+    /// Consumes the bytes in `[from, to)`, passing every chunk and its offset to `f`
+    fn scan<F: FnMut(u64, &[u8])>(&mut self, from: u64, to: u64, mut f: F) -> io::Result<()> {
+        self.seek(SeekFrom::Start(from))?;
+        while self.absolute_pos < to {
+            let pos = self.absolute_pos;
+            let n = {
+                let buf = self.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "offset is past the end of the stream"));
+                }
+                let n = (buf.len() as u64).min(to - pos) as usize;
+                f(pos, &buf[..n]);
+                n
+            };
+            self.consume(n);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn reader(data: &str, cap: usize) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(cap, Cursor::new(data.as_bytes().to_vec())).track_lines()
+    }
+
+    /// Line and column computed by brute force
+    fn expected(data: &str, offset: usize) -> (u64, u64) {
+        let before = &data.as_bytes()[..offset];
+        let line = before.iter().filter(|&&b| b == b'\n').count() as u64 + 1;
+        let start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        (line, (offset - start) as u64 + 1)
+    }
+
+    #[test]
+    fn consume_counts() {
+        let data = "ab\ncd\n\nef";
+        let mut r = reader(data, 4);
+        let mut line = String::new();
+        while r.read_line(&mut line).unwrap() > 0 {
+            let pos = r.position() as usize;
+            assert_eq!(r.line_col().unwrap(), expected(data, pos));
+        }
+        assert_eq!(r.line_col().unwrap(), (4, 3));
+    }
+
+    #[test]
+    fn seeks_in_both_directions() {
+        let data: String = (0..3000).map(|i| format!("{}\n", "x".repeat(i % 7))).collect();
+        let mut r = reader(&data, 64);
+        for &pos in &[5000, 10, 11000, 10999, 0, 7000, data.len()] {
+            r.seek(SeekFrom::Start(pos as u64)).unwrap();
+            assert_eq!(r.line_col().unwrap(), expected(&data, pos), "offset {}", pos);
+            assert_eq!(r.position(), pos as u64);
+        }
+        let tracker = r.tracker.as_ref().unwrap();
+        assert_eq!(tracker.checkpoints.len(), 3);
+        assert_eq!(tracker.scanned, data.len() as u64);
+    }
+
+    #[test]
+    fn direct_reads_and_primitives() {
+        let data = "a\nb\nc\nd\ne\n";
+        let mut r = reader(data, 2);
+        let mut buf = [0; 5];
+        r.read_exact(&mut buf).unwrap();
+        r.read_u16_le().unwrap();
+        assert_eq!(r.line_col().unwrap(), expected(data, 7));
+        assert_eq!(r.line_col_of(10).unwrap(), (6, 1));
+        let err = r.line_col_of(11).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut r = BufReader::new(Cursor::new(vec![0; 4]));
+        assert_eq!(r.line_col().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
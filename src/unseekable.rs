@@ -0,0 +1,211 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Buffering of non-seekable readers such as stdin, pipes or sockets.
+//!
+//! `Unseekable` provides forward seeks by skipping bytes, the `BufReader`
+//! keeps a history of consumed bytes to allow seeking back a limited distance.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use BufReader;
+
+/// Adapts a reader without `Seek` for use with `BufReader::with_history`.
+///
+/// Seeking forwards skips bytes, seeking backwards or relative to the end fails.
+pub struct Unseekable<R> {
+    inner: R,
+    pos: u64,       // number of bytes read or skipped
+}
+
+impl<R: Read> Unseekable<R> {
+    /// Wraps `inner`, its current position is treated as the start of the stream.
+    pub fn new(inner: R) -> Unseekable<R> {
+        Unseekable { inner, pos: 0 }
+    }
+
+    /// Unwraps this `Unseekable`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<R: Read> Read for Unseekable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for Unseekable<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) if n >= 0 => self.pos.saturating_add(n as u64),
+            SeekFrom::Current(n) => {
+                return Err(behind(self.pos.saturating_sub(n.unsigned_abs()), self.pos))
+            }
+            SeekFrom::End(_) => return Err(from_end()),
+        };
+        if target < self.pos {
+            return Err(behind(target, self.pos));
+        }
+        // Skipping past EOF behaves like seeking past the end of a file
+        io::copy(&mut (&mut self.inner).take(target - self.pos), &mut io::sink())?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+fn behind(target: u64, oldest: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported,
+                   format!("cannot seek to position {} of a non-seekable reader, \
+                            the oldest position which can be rewound to is {}", target, oldest))
+}
+
+fn from_end() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported,
+                   "cannot seek relative to the end of a non-seekable reader")
+}
+
+impl<R: Read> BufReader<Unseekable<R>> {
+    /// Creates a new `BufReader` for a reader without `Seek`, which keeps up to
+    /// `history` consumed bytes in addition to a buffer of `cap` bytes.
+    ///
+    /// Seeks within the buffer and its history are served from memory, seeking
+    /// forwards skips the bytes in between. Seeking further back or relative to
+    /// the end returns an error of the kind `Unsupported` and leaves the position
+    /// unchanged. `into_inner` fails the same way while the buffer holds bytes
+    /// ahead of the position, as they cannot be handed back to the reader.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{self, Read, Seek, SeekFrom};
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::with_history(4, 8, &b"#!/bin/sh\necho"[..]);
+    ///
+    /// let mut magic = [0; 6];
+    /// reader.read_exact(&mut magic).unwrap();
+    /// reader.seek(SeekFrom::Start(0)).unwrap();
+    ///
+    /// let mut script = String::new();
+    /// reader.read_to_string(&mut script).unwrap();
+    /// assert_eq!(script, "#!/bin/sh\necho");
+    ///
+    /// let err = reader.seek(SeekFrom::Start(0)).unwrap_err();
+    /// assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    /// ```
+    pub fn with_history(cap: usize, history: usize, inner: R) -> BufReader<Unseekable<R>> {
+        let mut reader = BufReader::with_capacity(cap, Unseekable::new(inner));
+        reader.history = Some(history);
+        reader
+    }
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Rejects seeks which non-seekable readers can't serve.
+    /// Positions behind the buffered window are only reachable by an inner seek.
+    pub(crate) fn check_history(&self, pos: SeekFrom) -> io::Result<()> {
+        if self.history.is_none() {
+            return Ok(());
+        }
+        let window_start = self.absolute_pos - self.buf_pos as u64;
+        match pos {
+            SeekFrom::Start(n) if n < window_start => Err(behind(n, window_start)),
+            SeekFrom::Start(_) => Ok(()),
+            _ => Err(from_end()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn rewind_within_history() {
+        let data = data(64);
+        let mut reader = BufReader::with_history(4, 6, &data[..]);
+
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf).unwrap();
+        // 6 bytes of history are kept across refills
+        assert_eq!(reader.seek(SeekFrom::Current(-6)).unwrap(), 4);
+        reader.read_exact(&mut buf[..3]).unwrap();
+        assert_eq!(buf[..3], [4, 5, 6]);
+
+        let err = reader.seek(SeekFrom::Start(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(reader.position(), 7);
+        reader.read_exact(&mut buf[..3]).unwrap();
+        assert_eq!(buf[..3], [7, 8, 9]);
+    }
+
+    #[test]
+    fn history_with_adaptive_growth() {
+        let data = data(64);
+        let mut reader = BufReader::with_history(4, 16, &data[..]).adaptive(4, 64);
+
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf[..5]).unwrap();
+        reader.read_exact(&mut buf[5..]).unwrap();
+        // Growing the fill size keeps the history
+        assert_eq!(reader.seek(SeekFrom::Start(0)).unwrap(), 0);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn seek_forward_skips() {
+        let data = data(64);
+        let mut reader = BufReader::with_history(4, 4, &data[..]);
+
+        assert_eq!(reader.seek(SeekFrom::Start(20)).unwrap(), 20);
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [20, 21]);
+        reader.seek(SeekFrom::Current(30)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [52, 53]);
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn seek_from_end() {
+        let data = data(8);
+        let mut reader = BufReader::with_history(4, 4, &data[..]);
+        let err = reader.seek(SeekFrom::End(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(reader.len().is_err());
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn into_inner_with_buffered_bytes() {
+        let data = data(16);
+        let mut reader = BufReader::with_history(4, 4, &data[..]);
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        // The whole window was consumed, so the reader can be handed back
+        let mut inner = reader.into_inner().unwrap();
+        inner.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5, 6, 7]);
+
+        let mut reader = BufReader::with_history(4, 4, &data[..]);
+        reader.read_exact(&mut buf[..1]).unwrap();
+        let err = reader.into_inner().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(err.to_string().ends_with("rewound to is 4"));
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2016 gcarq.
+// See the LICENSE file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! Variable-length integer decoding (LEB128, protobuf varints and zigzag).
+//!
+//! Bytes are decoded straight from the slice returned by `fill_buf`,
+//! encodings which straddle a refill are continued on the next window.
+
+use std::io::{self, BufRead, Read, Seek};
+
+use BufReader;
+
+/// Shift of the last byte which fits into 64 bits.
+const LAST_SHIFT: u32 = 63;
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    /// Decodes the 7-bit groups of a LEB128 value.
+    /// Returns the value, the shift of the last byte and the last byte itself.
+    fn read_leb128(&mut self) -> io::Result<(u64, u32, u8)> {
+        let mut value = 0u64;
+        let mut shift = 0u32;
+        loop {
+            let (consumed, last) = {
+                let buf = self.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "stream ended within a variable-length integer"));
+                }
+                let mut last = None;
+                let mut consumed = 0;
+                for &byte in buf {
+                    consumed += 1;
+                    value |= ((byte & 0x7f) as u64) << shift;
+                    if byte & 0x80 == 0 || shift == LAST_SHIFT {
+                        last = Some(byte);
+                        break;
+                    }
+                    shift += 7;
+                }
+                (consumed, last)
+            };
+            self.consume(consumed);
+            if let Some(byte) = last {
+                if byte & 0x80 != 0 {
+                    return Err(invalid_data("variable-length integer is longer than 10 bytes"));
+                }
+                return Ok((value, shift, byte));
+            }
+        }
+    }
+
+    /// Reads an unsigned LEB128 encoded integer, which is also the encoding of protobuf varints.
+    ///
+    /// Redundant padding bytes are accepted as long as the encoding fits into 10 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidData` if the encoding is longer than 10 bytes
+    /// or the value doesn't fit into an `u64`. The position is behind the offending byte then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new([0xe5, 0x8e, 0x26]));
+    /// assert_eq!(reader.read_uleb128().unwrap(), 624485);
+    /// ```
+    pub fn read_uleb128(&mut self) -> io::Result<u64> {
+        let (value, shift, last) = self.read_leb128()?;
+        if shift == LAST_SHIFT && last > 1 {
+            return Err(invalid_data("variable-length integer overflows 64 bits"));
+        }
+        Ok(value)
+    }
+
+    /// Reads a signed LEB128 encoded integer, as used by DWARF.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind `InvalidData` if the encoding is longer than 10 bytes
+    /// or the value doesn't fit into an `i64`. The position is behind the offending byte then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new([0xc0, 0xbb, 0x78]));
+    /// assert_eq!(reader.read_sleb128().unwrap(), -123456);
+    /// ```
+    pub fn read_sleb128(&mut self) -> io::Result<i64> {
+        let (mut value, shift, last) = self.read_leb128()?;
+        if shift == LAST_SHIFT {
+            // The remaining bits have to be a sign extension of bit 63
+            if last != 0 && last != 0x7f {
+                return Err(invalid_data("variable-length integer overflows 64 bits"));
+            }
+        } else if last & 0x40 != 0 {
+            value |= !0 << (shift + 7);
+        }
+        Ok(value as i64)
+    }
+
+    /// Reads a zigzag encoded varint, as used by protobuf `sint64` fields.
+    ///
+    /// # Errors
+    ///
+    /// See `read_uleb128`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use seek_bufread::BufReader;
+    ///
+    /// let mut reader = BufReader::new(Cursor::new([0x03, 0x04]));
+    /// assert_eq!(reader.read_zigzag().unwrap(), -2);
+    /// assert_eq!(reader.read_zigzag().unwrap(), 2);
+    /// ```
+    pub fn read_zigzag(&mut self) -> io::Result<i64> {
+        let n = self.read_uleb128()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn reader(data: &[u8], cap: usize) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(cap, Cursor::new(data.to_vec()))
+    }
+
+    #[test]
+    fn unsigned() {
+        let mut r = reader(&[0x00, 0x7f, 0x80, 0x01, 0xe5, 0x8e, 0x26, 0x80, 0x00], 16);
+        assert_eq!(r.read_uleb128().unwrap(), 0);
+        assert_eq!(r.read_uleb128().unwrap(), 127);
+        assert_eq!(r.read_uleb128().unwrap(), 128);
+        assert_eq!(r.read_uleb128().unwrap(), 624485);
+        // Padded encoding of 0
+        assert_eq!(r.read_uleb128().unwrap(), 0);
+        assert_eq!(r.position(), 9);
+    }
+
+    #[test]
+    fn unsigned_limits() {
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(reader(&max, 16).read_uleb128().unwrap(), u64::MAX);
+
+        let overflow = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        let err = reader(&overflow, 16).read_uleb128().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let overlong = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        let err = reader(&overlong, 16).read_uleb128().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn signed() {
+        let mut r = reader(&[0x02, 0x7e, 0xff, 0x00, 0x81, 0x7f, 0xc0, 0xbb, 0x78], 16);
+        assert_eq!(r.read_sleb128().unwrap(), 2);
+        assert_eq!(r.read_sleb128().unwrap(), -2);
+        assert_eq!(r.read_sleb128().unwrap(), 127);
+        assert_eq!(r.read_sleb128().unwrap(), -127);
+        assert_eq!(r.read_sleb128().unwrap(), -123456);
+    }
+
+    #[test]
+    fn signed_limits() {
+        let min = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f];
+        assert_eq!(reader(&min, 16).read_sleb128().unwrap(), i64::MIN);
+
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        assert_eq!(reader(&max, 16).read_sleb128().unwrap(), i64::MAX);
+
+        let overflow = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let err = reader(&overflow, 16).read_sleb128().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zigzag() {
+        let mut r = reader(&[0x00, 0x01, 0x02, 0x03, 0xfe, 0xff, 0xff, 0xff, 0x0f], 16);
+        assert_eq!(r.read_zigzag().unwrap(), 0);
+        assert_eq!(r.read_zigzag().unwrap(), -1);
+        assert_eq!(r.read_zigzag().unwrap(), 1);
+        assert_eq!(r.read_zigzag().unwrap(), -2);
+        assert_eq!(r.read_zigzag().unwrap(), 2147483647);
+    }
+
+    #[test]
+    fn across_refill() {
+        let mut r = reader(&[0x01, 0xe5, 0x8e, 0x26, 0xc0, 0xbb, 0x78, 0x80], 2);
+        assert_eq!(r.read_uleb128().unwrap(), 1);
+        assert_eq!(r.read_uleb128().unwrap(), 624485);
+        assert_eq!(r.read_sleb128().unwrap(), -123456);
+        assert_eq!(r.read_uleb128().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}